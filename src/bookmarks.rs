@@ -0,0 +1,552 @@
+// bookmarks, with titles, tags and folders. they are kept in the config
+// dir, one a line: the url, title, folder, tags and when it was added,
+// tab separated. folders nest with '/', as in "news/tech".
+
+use crate::cookie;
+use crate::tokenizer::{Token, Tokenizer};
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+pub struct Bookmark {
+    pub url: String,
+    pub title: String,
+    pub folder: String,
+    pub tags: Vec<String>,
+    pub added: i64,
+}
+
+impl Bookmark {
+    pub fn new(url: &str, title: &str) -> Bookmark {
+        Bookmark {
+            url: clean_url(url),
+            title: clean_title(title),
+            folder: String::new(),
+            tags: Vec::new(),
+            added: cookie::now(),
+        }
+    }
+
+    // the line it is listed with
+    pub fn line(&self) -> String {
+        let mut line = match self.folder.is_empty() {
+            true => self.title.clone(),
+            false => format!("{}/{}", self.folder, self.title),
+        };
+        write!(line, "  {}", self.url).unwrap();
+        for tag in &self.tags {
+            write!(line, " #{}", tag).unwrap();
+        }
+        line
+    }
+}
+
+pub struct Bookmarks {
+    file: Option<PathBuf>,
+    pub list: Vec<Bookmark>,
+}
+
+impl Bookmarks {
+    pub fn load(file: Option<PathBuf>) -> Bookmarks {
+        let mut bookmarks = Bookmarks {
+            file,
+            list: Vec::new(),
+        };
+        let text = bookmarks
+            .file
+            .as_ref()
+            .and_then(|f| fs::read_to_string(f).ok());
+        match text {
+            Some(text) => bookmarks.list = text.lines().filter_map(parse_line).collect(),
+            None => {
+                // the bookmark of old versions, kept in ~/.ibrow.conf
+                let old = home::home_dir()
+                    .and_then(|home| fs::read_to_string(home.join(".ibrow.conf")).ok());
+                if let Some(old) = old {
+                    for url in old.split_whitespace() {
+                        bookmarks.list.push(Bookmark::new(url, url));
+                    }
+                    let _ = bookmarks.save();
+                }
+            }
+        }
+        bookmarks
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut out = String::new();
+        for b in &self.list {
+            writeln!(
+                out,
+                "{}\t{}\t{}\t{}\t{}",
+                b.url,
+                b.title,
+                b.folder,
+                b.tags.join(","),
+                b.added
+            )
+            .unwrap();
+        }
+        fs::write(file, out)
+    }
+
+    pub fn add(&mut self, bookmark: Bookmark) {
+        self.list.push(bookmark);
+    }
+
+    pub fn remove(&mut self, index: usize) -> Bookmark {
+        self.list.remove(index)
+    }
+
+    // the bookmarks that fuzzily match query, best first, by index
+    pub fn search(&self, query: &str) -> Vec<usize> {
+        let mut found: Vec<(i64, usize)> = self
+            .list
+            .iter()
+            .enumerate()
+            .filter_map(|(i, b)| fuzzy(query, &b.line()).map(|score| (score, i)))
+            .collect();
+        found.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+        found.into_iter().map(|(_, i)| i).collect()
+    }
+
+    // adds the bookmarks of a netscape bookmark file, as other browsers
+    // export them. returns how many were new.
+    pub fn import_html(&mut self, html: &str) -> usize {
+        let mut tokenizer = Tokenizer::new();
+        tokenizer.feed(html);
+        tokenizer.end();
+
+        // a <dl> holds what is in the folder of the <h3> before it
+        let mut folders: Vec<Option<String>> = Vec::new();
+        let mut heading: Option<String> = None;
+        let mut folder_name: Option<String> = None;
+        let mut link: Option<Bookmark> = None;
+        let mut added = 0;
+        while let Some(token) = tokenizer.next_token() {
+            match token {
+                Token::StartTag(tag) => match tag.name.as_str() {
+                    "h3" => heading = Some(String::new()),
+                    "dl" => folders.push(folder_name.take()),
+                    "a" => {
+                        let attr = |name: &str| {
+                            tag.attrs
+                                .iter()
+                                .find(|(n, _)| n == name)
+                                .map(|(_, v)| v.as_str())
+                        };
+                        let Some(href) = attr("href") else {
+                            continue;
+                        };
+                        let mut b = Bookmark::new(href, "");
+                        b.folder = folders
+                            .iter()
+                            .flatten()
+                            .cloned()
+                            .collect::<Vec<_>>()
+                            .join("/");
+                        b.tags = split_tags(attr("tags").unwrap_or_default());
+                        if let Some(time) = attr("add_date").and_then(|t| t.parse().ok()) {
+                            b.added = time;
+                        }
+                        link = Some(b);
+                    }
+                    _ => (),
+                },
+                Token::EndTag(tag) => match tag.name.as_str() {
+                    "h3" => {
+                        folder_name = heading.take().map(|h| clean_title(&h).replace('/', "-"));
+                    }
+                    "dl" => {
+                        folders.pop();
+                    }
+                    "a" => {
+                        let Some(mut b) = link.take() else {
+                            continue;
+                        };
+                        // firefox keeps its smart folders as place: links
+                        if b.url.starts_with("place:") || b.url.starts_with("javascript:") {
+                            continue;
+                        }
+                        if b.title.is_empty() {
+                            b.title = b.url.clone();
+                        }
+                        let known = self
+                            .list
+                            .iter()
+                            .any(|old| old.url == b.url && old.folder == b.folder);
+                        if !known {
+                            self.list.push(b);
+                            added += 1;
+                        }
+                    }
+                    _ => (),
+                },
+                Token::Text(text) => {
+                    if let Some(h) = heading.as_mut() {
+                        h.push_str(&text);
+                    } else if let Some(b) = link.as_mut() {
+                        b.title = clean_title(&format!("{} {}", b.title, text));
+                    }
+                }
+                _ => (),
+            }
+        }
+        added
+    }
+
+    // the netscape bookmark file other browsers import
+    pub fn export_html(&self) -> String {
+        let mut out = String::from(
+            "<!DOCTYPE NETSCAPE-Bookmark-file-1>\n\
+             <META HTTP-EQUIV=\"Content-Type\" CONTENT=\"text/html; charset=UTF-8\">\n\
+             <TITLE>Bookmarks</TITLE>\n\
+             <H1>Bookmarks</H1>\n\
+             <DL><p>\n",
+        );
+        let folder = |b: &Bookmark| -> Vec<String> {
+            b.folder
+                .split('/')
+                .filter(|f| !f.is_empty())
+                .map(str::to_string)
+                .collect()
+        };
+        let mut sorted: Vec<&Bookmark> = self.list.iter().collect();
+        sorted.sort_by_key(|b| folder(b));
+
+        // the folders open at this point of the file
+        let mut open: Vec<String> = Vec::new();
+        for b in sorted {
+            let path = folder(b);
+            let shared = open.iter().zip(&path).take_while(|(a, b)| a == b).count();
+            while open.len() > shared {
+                open.pop();
+                writeln!(out, "{}</DL><p>", indent(open.len() + 1)).unwrap();
+            }
+            for name in &path[shared..] {
+                writeln!(
+                    out,
+                    "{}<DT><H3>{}</H3>",
+                    indent(open.len() + 1),
+                    escape(name)
+                )
+                .unwrap();
+                writeln!(out, "{}<DL><p>", indent(open.len() + 1)).unwrap();
+                open.push(name.clone());
+            }
+            write!(
+                out,
+                "{}<DT><A HREF=\"{}\" ADD_DATE=\"{}\"",
+                indent(open.len() + 1),
+                escape(&b.url),
+                b.added
+            )
+            .unwrap();
+            if !b.tags.is_empty() {
+                write!(out, " TAGS=\"{}\"", escape(&b.tags.join(","))).unwrap();
+            }
+            writeln!(out, ">{}</A>", escape(&b.title)).unwrap();
+        }
+        while !open.is_empty() {
+            open.pop();
+            writeln!(out, "{}</DL><p>", indent(open.len() + 1)).unwrap();
+        }
+        out.push_str("</DL><p>\n");
+        out
+    }
+}
+
+fn parse_line(line: &str) -> Option<Bookmark> {
+    let mut parts = line.split('\t');
+    let url = parts.next().filter(|u| !u.is_empty())?;
+    let mut b = Bookmark::new(url, parts.next().unwrap_or(url));
+    b.folder = parts.next().unwrap_or_default().to_string();
+    b.tags = split_tags(parts.next().unwrap_or_default());
+    b.added = parts.next().and_then(|t| t.parse().ok()).unwrap_or(0);
+    Some(b)
+}
+
+// tags are given separated by commas or spaces
+pub fn split_tags(s: &str) -> Vec<String> {
+    s.split(|c: char| c == ',' || c.is_whitespace())
+        .map(|t| t.trim_start_matches('#'))
+        .filter(|t| !t.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+// folders are given as a path, "news/tech"
+pub fn clean_folder(s: &str) -> String {
+    s.split('/')
+        .map(clean_title)
+        .filter(|f| !f.is_empty())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+// titles are one line, with no tabs to break up the file
+pub fn clean_title(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// urls lose their tabs and line breaks, as a url parser would drop them
+fn clean_url(s: &str) -> String {
+    s.trim().replace(['\t', '\n', '\r'], "")
+}
+
+fn indent(depth: usize) -> String {
+    "    ".repeat(depth)
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// whether the letters of query are all in text in order, and how good a
+// match that is: letters in a row and at the start of words count more
+pub fn fuzzy(query: &str, text: &str) -> Option<i64> {
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut pos = 0;
+    let mut last: Option<usize> = None;
+    for q in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let found = text[pos..].iter().position(|&c| c == q)? + pos;
+        score += 1;
+        if last.is_some_and(|l| l + 1 == found) {
+            score += 5;
+        }
+        if found == 0 || !text[found - 1].is_alphanumeric() {
+            score += 3;
+        }
+        last = Some(found);
+        pos = found + 1;
+    }
+    // shorter lines are closer matches
+    Some(score * 1000 - text.len() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bookmarks() -> Bookmarks {
+        Bookmarks {
+            file: None,
+            list: Vec::new(),
+        }
+    }
+
+    fn bookmark(url: &str, title: &str, folder: &str) -> Bookmark {
+        let mut b = Bookmark::new(url, title);
+        b.folder = folder.to_string();
+        b.added = 0;
+        b
+    }
+
+    // what is kept of each bookmark, in a set order
+    fn kept(bookmarks: &Bookmarks) -> Vec<(String, String, String, String, i64)> {
+        let mut kept: Vec<_> = bookmarks
+            .list
+            .iter()
+            .map(|b| {
+                let tags = b.tags.join(",");
+                (
+                    b.folder.clone(),
+                    b.url.clone(),
+                    b.title.clone(),
+                    tags,
+                    b.added,
+                )
+            })
+            .collect();
+        kept.sort();
+        kept
+    }
+
+    const FIREFOX: &str = "<!DOCTYPE NETSCAPE-Bookmark-file-1>
+<TITLE>Bookmarks</TITLE>
+<H1>Bookmarks Menu</H1>
+<DL><p>
+    <DT><A HREF=\"https://top.example/\" ADD_DATE=\"100\">Top</A>
+    <DT><H3>News</H3>
+    <DL><p>
+        <DT><A HREF=\"https://news.example/a
+b\" ADD_DATE=\"200\" TAGS=\"daily,world\">A &amp; B</A>
+        <DT><H3>Tech / Science</H3>
+        <DL><p>
+            <DT><A HREF=\" https://tech.example/\tx\">Tech</A>
+            <DT><A HREF=\"place:sort=8\">Recently Bookmarked</A>
+        </DL><p>
+    </DL><p>
+    <DT><A HREF=\"https://last.example/\">  Last
+        one </A>
+</DL><p>
+";
+
+    #[test]
+    fn import_html() {
+        let mut imported = bookmarks();
+        assert_eq!(imported.import_html(FIREFOX), 4);
+        let now = imported.list[2].added;
+        let b = |folder: &str, url: &str, title: &str, tags: &str, added| {
+            let s = str::to_string;
+            (s(folder), s(url), s(title), s(tags), added)
+        };
+        assert_eq!(
+            kept(&imported),
+            vec![
+                b(
+                    "",
+                    "https://last.example/",
+                    "Last one",
+                    "",
+                    imported.list[3].added
+                ),
+                b("", "https://top.example/", "Top", "", 100),
+                b(
+                    "News",
+                    "https://news.example/ab",
+                    "A & B",
+                    "daily,world",
+                    200
+                ),
+                b(
+                    "News/Tech - Science",
+                    "https://tech.example/x",
+                    "Tech",
+                    "",
+                    now
+                ),
+            ]
+        );
+        // the same links again are not new
+        assert_eq!(imported.import_html(FIREFOX), 0);
+        assert_eq!(imported.list.len(), 4);
+    }
+
+    #[test]
+    fn export_html_nests_folders() {
+        let mut list = bookmarks();
+        list.add(bookmark("https://x.example/", "x", "a/b"));
+        list.add(bookmark("https://y.example/?q=\"y\"", "y & z", "a"));
+        list.add(bookmark("https://z.example/", "z", ""));
+        let out = list.export_html();
+        assert!(out.starts_with("<!DOCTYPE NETSCAPE-Bookmark-file-1>\n"));
+        assert!(out.ends_with(
+            "<DL><p>
+    <DT><A HREF=\"https://z.example/\" ADD_DATE=\"0\">z</A>
+    <DT><H3>a</H3>
+    <DL><p>
+        <DT><A HREF=\"https://y.example/?q=&quot;y&quot;\" ADD_DATE=\"0\">y &amp; z</A>
+        <DT><H3>b</H3>
+        <DL><p>
+            <DT><A HREF=\"https://x.example/\" ADD_DATE=\"0\">x</A>
+        </DL><p>
+    </DL><p>
+</DL><p>
+"
+        ));
+    }
+
+    #[test]
+    fn export_then_import_keeps_everything() {
+        let mut first = bookmarks();
+        first.import_html(FIREFOX);
+        first.list[0].tags = vec!["a<b".to_string()];
+        let mut second = bookmarks();
+        assert_eq!(second.import_html(&first.export_html()), 4);
+        assert_eq!(kept(&second), kept(&first));
+        let mut third = bookmarks();
+        third.import_html(&second.export_html());
+        assert_eq!(kept(&third), kept(&first));
+    }
+
+    #[test]
+    fn lines_of_the_file() {
+        let b = parse_line("https://a.example/\tA\tnews/tech\tx,y\t123").unwrap();
+        assert_eq!(b.url, "https://a.example/");
+        assert_eq!(b.title, "A");
+        assert_eq!(b.folder, "news/tech");
+        assert_eq!(b.tags, ["x", "y"]);
+        assert_eq!(b.added, 123);
+
+        // older files have fewer fields
+        let b = parse_line("https://b.example/").unwrap();
+        assert_eq!(b.title, "https://b.example/");
+        assert_eq!(b.folder, "");
+        assert!(b.tags.is_empty());
+        assert_eq!(b.added, 0);
+        assert_eq!(
+            parse_line("https://c.example/\tC\t\t\tnot a time")
+                .unwrap()
+                .added,
+            0
+        );
+
+        assert!(parse_line("").is_none());
+        assert!(parse_line("\tno url").is_none());
+    }
+
+    #[test]
+    fn tabs_dont_break_up_the_file() {
+        let file = std::env::temp_dir().join(format!("ibrow-bookmarks-{}", std::process::id()));
+        let mut saved = Bookmarks {
+            file: Some(file.clone()),
+            list: Vec::new(),
+        };
+        let mut b = Bookmark::new("https://a.example/\tx\ny", "a\ttitle\non two lines");
+        b.folder = clean_folder("one\t/two");
+        b.tags = split_tags("t1\tt2");
+        saved.add(b);
+        saved.add(bookmark("https://b.example/", "b", ""));
+        saved.save().unwrap();
+        let loaded = Bookmarks::load(Some(file.clone()));
+        fs::remove_file(&file).unwrap();
+
+        assert_eq!(kept(&loaded), kept(&saved));
+        let b = &loaded.list[0];
+        assert_eq!(b.url, "https://a.example/xy");
+        assert_eq!(b.title, "a title on two lines");
+        assert_eq!(b.folder, "one/two");
+        assert_eq!(b.tags, ["t1", "t2"]);
+    }
+
+    #[test]
+    fn fuzzy_matches() {
+        assert!(fuzzy("gh", "GitHub").is_some());
+        assert!(fuzzy("g h", "github").is_some());
+        assert!(fuzzy("hg", "github").is_none());
+        assert!(fuzzy("x", "github").is_none());
+        // letters in a row, and at the start of words, are better
+        assert!(fuzzy("git", "github") > fuzzy("git", "good interest tip"));
+        assert!(fuzzy("rb", "rust book") > fuzzy("rb", "rubber"));
+        // and then the shorter text
+        assert!(fuzzy("a", "a b") > fuzzy("a", "a bc"));
+    }
+
+    #[test]
+    fn search_puts_the_best_first() {
+        let mut list = bookmarks();
+        list.add(bookmark("https://t.example/", "Trusty tools", ""));
+        list.add(bookmark("https://r.example/", "Rust", ""));
+        list.add(bookmark("https://b.example/", "The Rust book", ""));
+        list.add(bookmark("https://go.example/", "Go", ""));
+        assert_eq!(list.search("rust"), [1, 2, 0]);
+        // the folder and tags are searched as well
+        list.list[3].folder = "languages".to_string();
+        list.list[3].tags = vec!["compiled".to_string()];
+        assert_eq!(list.search("lang"), [3]);
+        assert_eq!(list.search("#comp"), [3]);
+        assert_eq!(list.search("").len(), 4);
+    }
+}
@@ -0,0 +1,892 @@
+// the tabs and everything they share, and what each command does to them
+
+use crate::bookmarks::{self, Bookmark, Bookmarks};
+use crate::cmdline;
+use crate::config::{self, Config};
+use crate::cookie::{self, Jar, SharedJar};
+use crate::encode;
+use crate::fetch::{self, Request};
+use crate::form::{Kind, Value};
+use crate::hints;
+use crate::input::*;
+use crate::keys::{Command, Keymap};
+use crate::page::{Page, Span, Spot, Target};
+use crate::pager;
+use crate::search::{self, Query};
+use crate::tab::{self, Tab};
+use crate::url::{self, Url};
+use crate::visits::{self, Visits};
+use crossterm::event::{MouseButton, MouseEvent, MouseEventKind};
+use crossterm::style::{ContentStyle, Stylize};
+use crossterm::terminal;
+use crossterm::{cursor, ExecutableCommand, QueueableCommand};
+use encoding_rs::UTF_8;
+use std::fs;
+use std::io;
+
+pub struct Browser {
+    pub tabs: Vec<Tab>,
+    pub active: usize,
+    pub keymap: Keymap,
+    // the height of the screen
+    pub rows: u16,
+    config: Config,
+    backend: String,
+    settings: fetch::Settings,
+    cookies: SharedJar,
+    visits: Visits,
+    bookmarks: Bookmarks,
+    // the lines of the command line run before
+    commands: cmdline::History,
+    // where a drag of the mouse started and where it is now
+    drag: Option<(Spot, Spot)>,
+    // the last search, for n and N
+    search: Option<Query>,
+}
+
+// how far a turn of the mouse wheel scrolls
+const WHEEL_LINES: i32 = 3;
+// how deep files can source other files, a file sourcing itself stops here
+const SOURCE_DEPTH: usize = 16;
+
+impl Browser {
+    pub fn new(config: Config, backend: String, rows: u16) -> Browser {
+        let settings = fetch::Settings {
+            user_agent: config.user_agent.clone(),
+            proxy: config.proxy.clone(),
+            headers: config.headers.clone(),
+        };
+        Browser {
+            tabs: Vec::new(),
+            active: 0,
+            keymap: Keymap::new(&config.keys),
+            rows,
+            backend,
+            settings,
+            cookies: Jar::load(config::dir().map(|dir| dir.join("cookies.txt"))).shared(),
+            visits: Visits::load(config::dir().map(|dir| dir.join("history"))),
+            bookmarks: Bookmarks::load(config::dir().map(|dir| dir.join("bookmarks"))),
+            commands: cmdline::History::load(config::dir().map(|dir| dir.join("commands"))),
+            drag: None,
+            search: None,
+            config,
+        }
+    }
+
+    // the tabs open last time come back, a file given on the command line
+    // opens in a tab of its own
+    pub fn restore(&mut self, path: Option<String>) -> io::Result<()> {
+        let (urls, mut active) = tab::load_session();
+        if path.is_some() {
+            active = urls.len();
+        }
+        for url in urls {
+            let mut tab = self.new_tab()?;
+            tab.visible = self.tabs.len() == active;
+            tab.reopen(url, &mut self.visits)?;
+            self.tabs.push(tab);
+        }
+        if let Some(path) = path {
+            let mut tab = self.new_tab()?;
+            tab.visible = true;
+            tab.open_file(&path, &mut self.visits)?;
+            self.tabs.push(tab);
+        }
+        if self.tabs.is_empty() {
+            let mut tab = self.new_tab()?;
+            tab.visible = true;
+            if let Some(url) = self.config.home_page.clone() {
+                tab.reopen(url, &mut self.visits)?;
+            }
+            self.tabs.push(tab);
+        }
+        self.active = active.min(self.tabs.len() - 1);
+        self.tabs[self.active].visible = true;
+        self.tabs[self.active].draw()?;
+        if self.tabs.len() > 1 {
+            pager::status(&tab::bar(&self.tabs, self.active))?;
+        }
+        Ok(())
+    }
+
+    // takes in what every tab fetched, returns whether any is still loading
+    pub fn poll(&mut self) -> io::Result<bool> {
+        let mut received = false;
+        for tab in self.tabs.iter_mut() {
+            received |= tab.poll(self.rows, &mut self.visits)?;
+        }
+        let loading = self.tabs.iter().any(Tab::is_loading);
+        if received && !loading {
+            save_cookies(&self.cookies)?;
+        }
+        Ok(loading)
+    }
+
+    pub fn tab(&self) -> &Tab {
+        &self.tabs[self.active]
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        self.cookies.lock().unwrap().save()?;
+        tab::save_session(&self.tabs, self.active)
+    }
+
+    // every tab fetches on a thread of its own, with the cookies all of
+    // them share
+    fn new_tab(&self) -> io::Result<Tab> {
+        let fetcher = fetch::from_name(&self.backend, &self.settings, self.cookies.clone())?;
+        Ok(Tab::new(fetcher))
+    }
+
+    fn switch_tab(&mut self, to: usize) -> io::Result<()> {
+        if let Some(tab) = self.tabs.get_mut(self.active) {
+            tab.visible = false;
+        }
+        self.active = to;
+        self.tabs[to].visible = true;
+        self.tabs[to].draw()?;
+        pager::status(&tab::bar(&self.tabs, to))
+    }
+
+    // returns false when it is time to exit
+    pub fn run(&mut self, command: Command) -> io::Result<bool> {
+        match command {
+            Command::Quit if self.tab().is_loading() => self.tabs[self.active].cancel()?,
+            Command::Quit => return Ok(false),
+            Command::NewTab => {
+                let complete = |s: &str| self.visits.complete(s);
+                let Ok(s) = get_input_completing("goto in new tab: ", None, &complete) else {
+                    return Ok(true);
+                };
+                match url::parse_input(&s) {
+                    Ok(url) => {
+                        let mut tab = self.new_tab()?;
+                        tab.load_page(Request::get(url))?;
+                        self.tabs.push(tab);
+                        self.switch_tab(self.tabs.len() - 1)?;
+                    }
+                    Err(e) => pager::status(&e.to_string())?,
+                }
+            }
+            Command::Follow => return self.follow(hints::Action::Follow),
+            Command::FollowNewTab => return self.follow(hints::Action::NewTab),
+            Command::NextTab => self.switch_tab((self.active + 1) % self.tabs.len())?,
+            Command::PrevTab => {
+                let prev = (self.active + self.tabs.len() - 1) % self.tabs.len();
+                self.switch_tab(prev)?;
+            }
+            Command::CloseTab => {
+                if self.tabs.len() == 1 {
+                    pager::status("can't close the last tab")?;
+                    return Ok(true);
+                }
+                let mut closed = self.tabs.remove(self.active);
+                closed.stop();
+                self.switch_tab(self.active.min(self.tabs.len() - 1))?;
+            }
+            Command::ListTabs => pager::status(&tab::bar(&self.tabs, self.active))?,
+            Command::Execute => {
+                let complete = |s: &str| cmdline::complete(s, &self.visits);
+                let Ok(line) = get_input_history(":", &complete, &self.commands.lines) else {
+                    return Ok(true);
+                };
+                if let Err(e) = self.commands.add(&line) {
+                    pager::status(&format!("can't save command history: {}", e))?;
+                }
+                return self.execute(&line);
+            }
+            Command::Search | Command::SearchBack => {
+                let mut query = self.search.clone().unwrap_or_default();
+                query.text.clear();
+                query.backward = command == Command::SearchBack;
+                let Some(mut query) = search::prompt(&mut self.tabs[self.active], query)? else {
+                    return Ok(true);
+                };
+                // nothing typed searches for the last query again
+                if query.text.is_empty() {
+                    let Some(last) = &self.search else {
+                        return Ok(true);
+                    };
+                    query.text = last.text.clone();
+                    self.search = Some(query);
+                    self.search_next(false)?;
+                    return Ok(true);
+                }
+                pager::status(&query.report(self.tab()))?;
+                self.search = Some(query);
+            }
+            Command::SearchNext => self.search_next(false)?,
+            Command::SearchPrev => self.search_next(true)?,
+            Command::Help => {
+                pager::show(&format!("{}\n{}", self.keymap.help(), cmdline::help()))?;
+                self.tab().draw()?;
+            }
+            _ => self.run_on_tab(command)?,
+        }
+        Ok(true)
+    }
+
+    // goes to the next match of the last search, in the direction it went
+    // or the other way. a page gone to since is searched again.
+    fn search_next(&mut self, other_way: bool) -> io::Result<()> {
+        let Some(query) = &self.search else {
+            return pager::status("no search yet, / or ? to search");
+        };
+        let tab = &mut self.tabs[self.active];
+        if tab.found.is_empty() {
+            tab.found = query.find(tab.shown()).unwrap_or_default();
+            tab.found_at = None;
+        }
+        if let Some(i) = search::next(tab, query.backward != other_way)? {
+            tab.go_to_found(i)?;
+        }
+        pager::status(&query.report(tab))
+    }
+
+    // a click follows a link or edits a field, the wheel scrolls and a drag
+    // selects text, which is copied when the button is let go
+    pub fn mouse(&mut self, event: MouseEvent) -> io::Result<()> {
+        let (width, height) = terminal::size()?;
+        let (width, height) = (usize::from(width).max(1), height);
+        let tab = &mut self.tabs[self.active];
+        let page = tab.shown();
+        let shown = pager::layout(&page.buf, tab.line, width, height.into());
+        // the last row is the status line, a drag over it stays on the page
+        let row = event.row.min(height.saturating_sub(2));
+        let spot = pager::spot_at(&shown, row, event.column);
+        match (event.kind, spot) {
+            (MouseEventKind::ScrollDown, _) => tab.scroll(WHEEL_LINES)?,
+            (MouseEventKind::ScrollUp, _) => tab.scroll(-WHEEL_LINES)?,
+            (MouseEventKind::Down(MouseButton::Left), Some(spot)) => {
+                self.drag = Some((spot, spot));
+            }
+            (MouseEventKind::Drag(MouseButton::Left), Some(spot)) => {
+                let Some((start, _)) = self.drag else {
+                    return Ok(());
+                };
+                self.drag = Some((start, spot));
+                tab.draw()?;
+                let selected = ContentStyle::new().reverse();
+                pager::highlight(&page.buf, tab.line, &[(selection(start, spot), selected)])?;
+            }
+            (MouseEventKind::Up(MouseButton::Left), _) => match self.drag.take() {
+                Some((start, end)) if start != end => {
+                    let text = page.text(selection(start, end));
+                    tab.draw()?;
+                    pager::copy(&text)?;
+                    pager::status(&format!("copied {} characters", text.chars().count()))?;
+                }
+                Some((spot, _)) => {
+                    if let Some(target) = page.target_at(spot) {
+                        tab.focus = Some(target);
+                        tab.draw()?;
+                        self.run_on_tab(Command::OpenFocused)?;
+                    }
+                }
+                None => (),
+            },
+            _ => (),
+        }
+        Ok(())
+    }
+
+    // picks a link on screen by its label and does action with it
+    fn follow(&mut self, action: hints::Action) -> io::Result<bool> {
+        let tab = &self.tabs[self.active];
+        let picked = hints::pick(tab.shown(), tab.line, action)?;
+        tab.draw()?;
+        let Some((i, action)) = picked else {
+            return Ok(true);
+        };
+        let page = tab.shown();
+        let url = match page.resolve(&page.anchors[i]) {
+            Ok(url) => url,
+            Err(e) => return pager::status(&e.to_string()).map(|_| true),
+        };
+        match action {
+            hints::Action::Follow => self.tabs[self.active].load_page(Request::get(url))?,
+            hints::Action::NewTab => {
+                // the link opens behind the tab it is on
+                let mut tab = self.new_tab()?;
+                tab.load_page(Request::get(url))?;
+                self.tabs.insert(self.active + 1, tab);
+                pager::status(&tab::bar(&self.tabs, self.active))?;
+            }
+            hints::Action::Copy => {
+                pager::copy(&url.to_string())?;
+                pager::status(&format!("copied {}", url))?;
+            }
+            hints::Action::Download => {
+                let dir = self.config.download_dir.clone();
+                self.tabs[self.active].download(Request::get(url), dir.as_deref())?;
+            }
+        }
+        Ok(true)
+    }
+
+    // runs a line of the command line, returns false when it is time to
+    // exit. what went wrong goes on the status line.
+    pub fn execute(&mut self, line: &str) -> io::Result<bool> {
+        match self.execute_line(line, 0)? {
+            Ok(go_on) => Ok(go_on),
+            Err(e) => pager::status(&e).map(|_| true),
+        }
+    }
+
+    // depth is how many sourced files deep the line is
+    fn execute_line(&mut self, line: &str, depth: usize) -> io::Result<Result<bool, String>> {
+        let (name, args) = cmdline::parse(line);
+        let command = Command::from_name(name);
+        match (name, args) {
+            ("", _) => (),
+            ("open", "") => return self.run(Command::Goto).map(Ok),
+            ("open", url) => match url::parse_input(url) {
+                Ok(url) => self.tabs[self.active].load_page(Request::get(url))?,
+                Err(e) => return Ok(Err(e.to_string())),
+            },
+            ("tabnew", url) => {
+                let url = match url {
+                    "" => None,
+                    url => match url::parse_input(url) {
+                        Ok(url) => Some(url),
+                        Err(e) => return Ok(Err(e.to_string())),
+                    },
+                };
+                let mut tab = self.new_tab()?;
+                if let Some(url) = url {
+                    tab.load_page(Request::get(url))?;
+                }
+                self.tabs.push(tab);
+                self.switch_tab(self.tabs.len() - 1)?;
+            }
+            ("bookmark", args) => return self.bookmark(args),
+            ("set", args) => return self.set(args),
+            ("save", "") | ("source", "") => return Ok(Err(format!("{} what file?", name))),
+            ("save", file) => return self.save_page(file),
+            ("source", file) if depth >= SOURCE_DEPTH => {
+                return Ok(Err(format!(
+                    "{}: sourced more than {} deep",
+                    file, SOURCE_DEPTH
+                )))
+            }
+            ("source", file) => {
+                let text = match fs::read_to_string(file) {
+                    Ok(text) => text,
+                    Err(e) => return Ok(Err(format!("{}: {}", file, e))),
+                };
+                // lines starting with # are comments
+                for (i, line) in text.lines().enumerate() {
+                    if line.trim().is_empty() || line.trim().starts_with('#') {
+                        continue;
+                    }
+                    match self.execute_line(line, depth + 1)? {
+                        Ok(true) => (),
+                        Ok(false) => return Ok(Ok(false)),
+                        // a sourced file already says where in it
+                        Err(e) if matches!(cmdline::parse(line), ("source", f) if !f.is_empty()) => {
+                            return Ok(Err(e))
+                        }
+                        Err(e) => return Ok(Err(format!("{}:{}: {}", file, i + 1, e))),
+                    }
+                }
+            }
+            (_, "") if command.is_some() => return self.run(command.unwrap()).map(Ok),
+            (_, _) if command.is_some() => return Ok(Err(format!("{} takes no arguments", name))),
+            _ => return Ok(Err(format!("not a command: {}", name))),
+        }
+        Ok(Ok(true))
+    }
+
+    fn bookmark(&mut self, args: &str) -> io::Result<Result<bool, String>> {
+        let (what, arg) = args.split_once(' ').unwrap_or((args, ""));
+        let arg = arg.trim();
+        let page = self.tabs[self.active].history.current();
+        match what {
+            "" => return self.run(Command::Bookmarks).map(Ok),
+            "add" if arg.is_empty() => add_bookmark(&mut self.bookmarks, page)?,
+            "add" if page.info.url.scheme() == "about" => {
+                return Ok(Err("nothing to bookmark".to_string()))
+            }
+            "add" => {
+                let bookmark = Bookmark::new(&page.info.url.to_string(), arg);
+                pager::status(&format!("bookmarked {}", bookmark.line()))?;
+                self.bookmarks.add(bookmark);
+                save_bookmarks(&self.bookmarks)?;
+            }
+            "open" => {
+                let Some(&i) = self.bookmarks.search(arg).first() else {
+                    return Ok(Err(format!("no bookmark matches {}", arg)));
+                };
+                match url::parse_input(&self.bookmarks.list[i].url) {
+                    Ok(url) => self.tabs[self.active].load_page(Request::get(url))?,
+                    Err(e) => return Ok(Err(e.to_string())),
+                }
+            }
+            _ => return Ok(Err(format!("bookmark {}: not add or open", what))),
+        }
+        Ok(Ok(true))
+    }
+
+    // set shows every setting, set name shows one and set name=value
+    // changes it
+    fn set(&mut self, args: &str) -> io::Result<Result<bool, String>> {
+        if args.is_empty() {
+            let mut buf = String::from("settings\n\n");
+            for name in config::SETTINGS {
+                let value = self.config.get(name).unwrap_or_default();
+                buf.push_str(&format!("{} = {}\n", name, value));
+            }
+            pager::show(&buf)?;
+            self.tab().draw()?;
+            return Ok(Ok(true));
+        }
+        let Some((name, value)) = args.split_once('=') else {
+            return match self.config.get(args) {
+                Ok(value) => pager::status(&format!("{}={}", args, value)).map(|_| Ok(true)),
+                Err(e) => Ok(Err(e)),
+            };
+        };
+        let name = match self.config.set(name.trim(), value.trim()) {
+            Ok(name) => name,
+            Err(e) => return Ok(Err(e)),
+        };
+        // what fetches see is made anew for every tab
+        if let "user_agent" | "proxy" | "fetcher" = name {
+            self.settings.user_agent = self.config.user_agent.clone();
+            self.settings.proxy = self.config.proxy.clone();
+            if name == "fetcher" {
+                self.backend = self.config.fetcher.clone();
+            }
+            for i in 0..self.tabs.len() {
+                let fetcher =
+                    fetch::from_name(&self.backend, &self.settings, self.cookies.clone())?;
+                self.tabs[i].set_fetcher(fetcher);
+            }
+        }
+        let value = self.config.get(name).unwrap_or_default();
+        pager::status(&format!("{}={}", name, value))?;
+        Ok(Ok(true))
+    }
+
+    // fetches the page again into file, a local one is copied
+    fn save_page(&mut self, file: &str) -> io::Result<Result<bool, String>> {
+        let tab = &mut self.tabs[self.active];
+        let url = tab.history.current().info.url.clone();
+        match url.scheme() {
+            "about" => return Ok(Err("nothing to save".to_string())),
+            "file" => {
+                let path = String::from_utf8_lossy(&url::percent_decode(url.path())).into_owned();
+                match fs::copy(&path, file) {
+                    Ok(bytes) => pager::status(&format!("saved {} ({} bytes)", file, bytes))?,
+                    Err(e) => return Ok(Err(format!("{}: {}", file, e))),
+                }
+            }
+            _ => tab.save(Request::get(url), file.to_string())?,
+        }
+        Ok(Ok(true))
+    }
+
+    // the commands that work on the page of the tab on screen
+    fn run_on_tab(&mut self, command: Command) -> io::Result<()> {
+        let rows = self.rows;
+        let download_dir = self.config.download_dir.as_deref();
+        let visits = &mut self.visits;
+        let bookmarks = &mut self.bookmarks;
+        let cookies = &self.cookies;
+        let tab = &mut self.tabs[self.active];
+        let last_line = (tab.shown().line_count as u16).saturating_sub(1);
+        match command {
+            Command::Cancel if tab.is_loading() => tab.cancel()?,
+            Command::Cancel if !tab.found.is_empty() => {
+                tab.found.clear();
+                tab.found_at = None;
+                tab.draw()?;
+            }
+            Command::ScrollDown => tab.scroll(1)?,
+            Command::ScrollUp => tab.scroll(-1)?,
+            Command::PageDown => tab.scroll(i32::from(rows / 2))?,
+            Command::PageUp => tab.scroll(-i32::from(rows / 2))?,
+            Command::Top => {
+                tab.line = 0;
+                tab.draw()?;
+            }
+            Command::Bottom => {
+                // the last screenful
+                tab.line = last_line.saturating_sub(rows.saturating_sub(2));
+                tab.draw()?;
+            }
+            Command::FocusNext => tab.move_focus(true)?,
+            Command::FocusPrev => tab.move_focus(false)?,
+            Command::OpenFocused => match tab.focus {
+                Some(Target::Anchor(i)) => {
+                    let page = tab.shown();
+                    match page.resolve(&page.anchors[i]) {
+                        Ok(url) => tab.load_page(Request::get(url))?,
+                        Err(e) => pager::status(&e.to_string())?,
+                    }
+                }
+                Some(Target::Control(i)) => {
+                    // focus is picked on the page on screen, which may be
+                    // the one loading
+                    tab.keep_shown();
+                    match edit_field(tab.history.current_mut(), i)? {
+                        Some(req) => tab.load_page(req)?,
+                        None => tab.draw()?,
+                    }
+                }
+                None => pager::status("nothing picked out, tab or j picks a link")?,
+            },
+            Command::Back => tab.back()?,
+            Command::Forward => tab.forward()?,
+            Command::OpenFile => {
+                let Ok(path) = get_input("files: ") else {
+                    return Ok(());
+                };
+                tab.open_file(&path, visits)?;
+                io::stdout().execute(cursor::MoveToColumn(0))?;
+            }
+            Command::Goto | Command::GotoCurrent => {
+                let current = tab.history.current().info.url.to_string();
+                let start = match command {
+                    Command::GotoCurrent => Some(current.as_str()),
+                    _ => None,
+                };
+                let complete = |s: &str| visits.complete(s);
+                let Ok(s) = get_input_completing("goto: ", start, &complete) else {
+                    return Ok(());
+                };
+                match url::parse_input(&s) {
+                    Ok(url) => tab.load_page(Request::get(url))?,
+                    Err(e) => pager::status(&e.to_string())?,
+                }
+            }
+            Command::PostData => {
+                let Ok(data) = get_input("data: ") else {
+                    return Ok(());
+                };
+                let url = tab.history.current().info.url.clone();
+                match post(url, &data) {
+                    Ok(req) => tab.load_page(req)?,
+                    Err(e) => pager::status(&e.to_string())?,
+                }
+            }
+            Command::Bookmarks => {
+                let Some(i) = pick_bookmark(bookmarks, "bookmarks: ")? else {
+                    return tab.draw();
+                };
+                match url::parse_input(&bookmarks.list[i].url) {
+                    Ok(url) => tab.load_page(Request::get(url))?,
+                    Err(e) => pager::status(&e.to_string())?,
+                }
+            }
+            Command::Bookmark => add_bookmark(bookmarks, tab.history.current())?,
+            Command::ManageBookmarks => {
+                let msg = manage_bookmarks(bookmarks)?;
+                tab.draw()?;
+                if let Some(msg) = msg {
+                    pager::status(&msg)?;
+                }
+            }
+            Command::EditField => {
+                let Ok(s) = get_input("field index: ") else {
+                    return Ok(());
+                };
+                let Ok(index) = s.parse::<usize>() else {
+                    return Ok(());
+                };
+                // the index is read off the screen
+                tab.keep_shown();
+                match edit_field(tab.history.current_mut(), index)? {
+                    Some(req) => tab.load_page(req)?,
+                    None => tab.draw()?,
+                }
+            }
+            Command::Submit => {
+                let Ok(s) = get_input("submit (field index): ") else {
+                    return Ok(());
+                };
+                tab.keep_shown();
+                let page = tab.history.current();
+                let req = match s.parse::<usize>() {
+                    Ok(index) => match page.controls.get(index) {
+                        Some(&id) => submit_form(page, id)?,
+                        None => None,
+                    },
+                    // without an index, the first form on the page
+                    Err(_) if !page.forms.is_empty() => request_or_status(page.submit(0, None))?,
+                    Err(_) => None,
+                };
+                match req {
+                    Some(req) => tab.load_page(req)?,
+                    None => tab.draw()?,
+                }
+            }
+            Command::Cookies => {
+                pager::pager(&cookies_buf(cookies), 0)?;
+                if let Ok(s) = get_input("delete cookies of (site [name]): ") {
+                    let mut words = s.split_whitespace();
+                    let site = words.next().unwrap_or_default();
+                    let removed = cookies.lock().unwrap().remove(site, words.next());
+                    tab.draw()?;
+                    pager::status(&format!("deleted {} cookies", removed))?;
+                    save_cookies(cookies)?;
+                } else {
+                    tab.draw()?;
+                }
+            }
+            Command::Info => {
+                pager::show(&tab.shown().info_buf())?;
+                tab.draw()?;
+            }
+            Command::History => {
+                let picked = pick("history: ", |query| {
+                    visits
+                        .search(query)
+                        .into_iter()
+                        .map(|v| (visit_line(v), v.url.clone()))
+                        .collect()
+                })?;
+                match picked.map(|url| Url::parse(&url)) {
+                    Some(Ok(url)) => tab.load_page(Request::get(url))?,
+                    Some(Err(e)) => pager::status(&e.to_string())?,
+                    None => tab.draw()?,
+                }
+            }
+            Command::Download => {
+                let url = tab.history.current().info.url.to_string();
+                let Ok(s) = get_input_with("download: ", Some(&url)) else {
+                    return Ok(());
+                };
+                match url::parse_input(&s) {
+                    Ok(url) => tab.download(Request::get(url), download_dir)?,
+                    Err(e) => pager::status(&e.to_string())?,
+                }
+            }
+            Command::PostDownload => {
+                io::stdout().queue(cursor::MoveTo(0, 1))?;
+                print!("{}", &tab.history.current().info.url);
+                let Ok(s) = get_input("data and download: ") else {
+                    return Ok(());
+                };
+                let url = tab.history.current().info.url.clone();
+                let req = Request::post(url, encode::URLENCODED, s.into_bytes());
+                tab.download(req, download_dir)?;
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+}
+
+// the text from one spot to another, the one under the mouse included
+fn selection(from: Spot, to: Spot) -> Span {
+    let (start, end) = (from.min(to), from.max(to));
+    let end = Spot {
+        line: end.line,
+        column: end.column + 1,
+    };
+    Span { start, end }
+}
+
+fn save_cookies(cookies: &SharedJar) -> io::Result<()> {
+    match cookies.lock().unwrap().save() {
+        Ok(()) => Ok(()),
+        Err(e) => pager::status(&format!("can't save cookies: {}", e)),
+    }
+}
+
+fn cookies_buf(cookies: &SharedJar) -> String {
+    let jar = cookies.lock().unwrap();
+    let mut buf = format!("{}\n", "cookies".bold());
+    let mut site = "";
+    for c in jar.list() {
+        if c.domain != site {
+            site = &c.domain;
+            buf.push_str(&format!("\n{}\n", site.bold()));
+        }
+        let value: String = c.value.chars().take(40).collect();
+        let expires = match c.expires {
+            Some(t) => {
+                let (y, m, d) = cookie::civil_from_unix(t);
+                format!("until {}-{:02}-{:02}", y, m, d)
+            }
+            None => "session".to_string(),
+        };
+        let mut flags = String::new();
+        if c.secure {
+            flags.push_str(" secure");
+        }
+        if c.http_only {
+            flags.push_str(" httponly");
+        }
+        buf.push_str(&format!(
+            "  {}={}  {}  {}{}\n",
+            c.name, value, c.path, expires, flags
+        ));
+    }
+    buf
+}
+
+fn pick_bookmark(bookmarks: &Bookmarks, prompt: &str) -> io::Result<Option<usize>> {
+    pick(prompt, |query| {
+        bookmarks
+            .search(query)
+            .into_iter()
+            .map(|i| (bookmarks.list[i].line(), i))
+            .collect()
+    })
+}
+
+fn save_bookmarks(bookmarks: &Bookmarks) -> io::Result<()> {
+    match bookmarks.save() {
+        Ok(()) => Ok(()),
+        Err(e) => pager::status(&format!("can't save bookmarks: {}", e)),
+    }
+}
+
+// bookmarks page, asking for its title, folder and tags
+fn add_bookmark(bookmarks: &mut Bookmarks, page: &Page) -> io::Result<()> {
+    if page.info.url.scheme() == "about" {
+        return pager::status("nothing to bookmark");
+    }
+    let Ok(title) = get_input_with("title: ", Some(&page.title())) else {
+        return Ok(());
+    };
+    let mut bookmark = Bookmark::new(&page.info.url.to_string(), &title);
+    // these two may be left empty
+    bookmark.folder = bookmarks::clean_folder(&get_input("folder: ").unwrap_or_default());
+    bookmark.tags = bookmarks::split_tags(&get_input("tags: ").unwrap_or_default());
+    pager::status(&format!("bookmarked {}", bookmark.line()))?;
+    bookmarks.add(bookmark);
+    save_bookmarks(bookmarks)
+}
+
+// returns what to say about it once the page is drawn again
+fn manage_bookmarks(bookmarks: &mut Bookmarks) -> io::Result<Option<String>> {
+    let Ok(s) = get_input("bookmarks: (d)elete (r)ename (m)ove (t)ag (i)mport (e)xport: ") else {
+        return Ok(None);
+    };
+    let msg = match s.as_str() {
+        "d" | "r" | "m" | "t" => {
+            let Some(i) = pick_bookmark(bookmarks, &format!("{}: ", s))? else {
+                return Ok(None);
+            };
+            let b = &mut bookmarks.list[i];
+            match s.as_str() {
+                "d" => format!("deleted {}", bookmarks.remove(i).title),
+                "r" => match get_input_with("title: ", Some(&b.title)) {
+                    Ok(title) => {
+                        b.title = bookmarks::clean_title(&title);
+                        format!("renamed to {}", b.title)
+                    }
+                    Err(_) => return Ok(None),
+                },
+                // "/" moves it out of any folder
+                "m" => match get_input_with("folder: ", Some(&b.folder)) {
+                    Ok(folder) => {
+                        b.folder = bookmarks::clean_folder(&folder);
+                        format!("moved to {}/", b.folder)
+                    }
+                    Err(_) => return Ok(None),
+                },
+                _ => match get_input_with("tags: ", Some(&b.tags.join(" "))) {
+                    Ok(tags) => {
+                        b.tags = bookmarks::split_tags(&tags);
+                        b.line()
+                    }
+                    Err(_) => return Ok(None),
+                },
+            }
+        }
+        "i" => {
+            let Ok(path) = get_input("import from: ") else {
+                return Ok(None);
+            };
+            match fs::read(&path) {
+                Ok(html) => {
+                    let added = bookmarks.import_html(&String::from_utf8_lossy(&html));
+                    format!("imported {} bookmarks", added)
+                }
+                Err(e) => return Ok(Some(format!("{}: {}", path, e))),
+            }
+        }
+        "e" => {
+            let Ok(path) = get_input_with("export to: ", Some("bookmarks.html")) else {
+                return Ok(None);
+            };
+            let msg = match fs::write(&path, bookmarks.export_html()) {
+                Ok(()) => format!("exported {} bookmarks to {}", bookmarks.list.len(), path),
+                Err(e) => format!("{}: {}", path, e),
+            };
+            return Ok(Some(msg));
+        }
+        _ => return Ok(None),
+    };
+    match bookmarks.save() {
+        Ok(()) => Ok(Some(msg)),
+        Err(e) => Ok(Some(format!("can't save bookmarks: {}", e))),
+    }
+}
+
+// the date it was last visited, how many times, the title and the url
+fn visit_line(visit: &visits::Visit) -> String {
+    let (y, m, d) = cookie::civil_from_unix(visit.time);
+    format!(
+        "{}-{:02}-{:02} {:>4}  {}  {}",
+        y, m, d, visit.count, visit.title, visit.url
+    )
+}
+
+// sends data as a single multipart field, like `curl -F name=value`
+fn post(url: Url, data: &str) -> io::Result<Request> {
+    let (name, value) = data.split_once('=').unwrap_or((data, ""));
+    let entries = [(name.to_string(), Value::Text(value.to_string()))];
+    let (content_type, body) = encode::multipart(&entries, UTF_8)?;
+    Ok(Request::post(url, &content_type, body))
+}
+
+// fills in a field, or sends its form when it is a submit button
+fn edit_field(page: &mut Page, control: usize) -> io::Result<Option<Request>> {
+    let Some(field) = page.field(control) else {
+        pager::status(&format!("no field {}", control))?;
+        return Ok(None);
+    };
+    let id = page.controls[control];
+    let current = match field.kind {
+        _ if field.is_submit() => return submit_form(page, id),
+        Kind::Checkbox | Kind::Radio => {
+            page.toggle(control);
+            return Ok(None);
+        }
+        Kind::Reset | Kind::Button => return Ok(None),
+        Kind::Select => {
+            pager::status(&field.option_list())?;
+            field.selected_labels()
+        }
+        _ => field.value.clone(),
+    };
+    let prompt = format!("{}: ", field.name);
+    let Ok(input) = get_input_with(&prompt, Some(&current)) else {
+        return Ok(None);
+    };
+    if let Err(e) = page.fill(control, &input) {
+        pager::status(&e)?;
+    }
+    Ok(None)
+}
+
+fn submit_form(page: &Page, id: usize) -> io::Result<Option<Request>> {
+    let Some(form) = page.fields[id].form else {
+        pager::status("the field is not in a form")?;
+        return Ok(None);
+    };
+    let submitter = Some(id).filter(|&id| page.fields[id].is_submit());
+    request_or_status(page.submit(form, submitter))
+}
+
+fn request_or_status(req: io::Result<Request>) -> io::Result<Option<Request>> {
+    match req {
+        Ok(req) => Ok(Some(req)),
+        Err(e) => pager::status(&e.to_string()).map(|_| None),
+    }
+}
@@ -0,0 +1,267 @@
+// character set detection: byte order mark, then the content-type header,
+// then a <meta> prescan of the first bytes, like the spec's encoding sniffing.
+
+use crate::tokenizer::{Token, Tokenizer};
+use encoding_rs::{Decoder, Encoding, UTF_8, WINDOWS_1252};
+
+const PRESCAN_LEN: usize = 1024;
+
+// decodes a body that arrives in pieces. bytes are held back until the
+// encoding can be told, which takes up to PRESCAN_LEN bytes without a
+// bom or a charset in the content type.
+pub struct StreamDecoder {
+    declared: Option<&'static Encoding>,
+    pending: Vec<u8>,
+    decoder: Option<Decoder>,
+    had_errors: bool,
+}
+
+impl StreamDecoder {
+    pub fn new(content_type: Option<&str>) -> StreamDecoder {
+        StreamDecoder {
+            declared: content_type.and_then(charset_param),
+            pending: Vec::new(),
+            decoder: None,
+            had_errors: false,
+        }
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) -> String {
+        if self.decoder.is_none() {
+            self.pending.extend_from_slice(bytes);
+            if self.pending.len() < PRESCAN_LEN
+                && (self.declared.is_none() || self.pending.len() < 3)
+            {
+                return String::new();
+            }
+            let pending = std::mem::take(&mut self.pending);
+            self.start(&pending);
+            return self.decode(&pending, false);
+        }
+        self.decode(bytes, false)
+    }
+
+    pub fn finish(&mut self) -> String {
+        if self.decoder.is_none() {
+            let pending = std::mem::take(&mut self.pending);
+            self.start(&pending);
+            return self.decode(&pending, true);
+        }
+        self.decode(&[], true)
+    }
+
+    pub fn encoding(&self) -> &'static str {
+        match &self.decoder {
+            Some(decoder) => decoder.encoding().name(),
+            None => UTF_8.name(),
+        }
+    }
+
+    pub fn warning(&self) -> Option<String> {
+        match self.had_errors {
+            true => Some(format!(
+                "page is not valid {}, some characters were replaced",
+                self.encoding()
+            )),
+            false => None,
+        }
+    }
+
+    fn start(&mut self, head: &[u8]) {
+        let encoding = Encoding::for_bom(head)
+            .map(|(encoding, _)| encoding)
+            .or(self.declared)
+            .or_else(|| prescan(head))
+            .unwrap_or_else(|| match std::str::from_utf8(head) {
+                Ok(_) => UTF_8,
+                // a multi-byte character may be cut off at the end
+                Err(e) if e.error_len().is_none() => UTF_8,
+                Err(_) => WINDOWS_1252,
+            });
+        // the decoder strips a bom itself, and swaps the encoding if it finds one
+        self.decoder = Some(encoding.new_decoder());
+    }
+
+    fn decode(&mut self, bytes: &[u8], last: bool) -> String {
+        let decoder = self.decoder.as_mut().unwrap();
+        let len = decoder
+            .max_utf8_buffer_length(bytes.len())
+            .unwrap_or(bytes.len() * 3 + 16);
+        let mut out = String::with_capacity(len);
+        let (_, _, had_errors) = decoder.decode_to_string(bytes, &mut out, last);
+        self.had_errors |= had_errors;
+        out
+    }
+}
+
+fn charset_param(content_type: &str) -> Option<&'static Encoding> {
+    let lower = content_type.to_ascii_lowercase();
+    let idx = lower.find("charset")?;
+    let rest = lower.get(idx + "charset".len()..)?.trim_start();
+    let rest = rest.strip_prefix('=')?.trim_start();
+    let label = match rest.strip_prefix(['"', '\'']) {
+        Some(quoted) => quoted.split(['"', '\'']).next()?,
+        None => rest.split([';', ' ', '\t']).next()?,
+    };
+    Encoding::for_label(label.as_bytes())
+}
+
+fn prescan(bytes: &[u8]) -> Option<&'static Encoding> {
+    let head = &bytes[..bytes.len().min(PRESCAN_LEN)];
+    // every byte maps to one char in latin-1, which is all the prescan needs
+    let head: String = head.iter().map(|&b| b as char).collect();
+    let mut tokenizer = Tokenizer::new();
+    tokenizer.feed(&head);
+    tokenizer.end();
+
+    while let Some(token) = tokenizer.next_token() {
+        let Token::StartTag(tag) = token else {
+            continue;
+        };
+        if tag.name != "meta" {
+            continue;
+        }
+        let attr = |name: &str| {
+            tag.attrs
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.as_str())
+        };
+        let encoding = match attr("charset") {
+            Some(label) => Encoding::for_label(label.trim().as_bytes()),
+            None => match attr("http-equiv") {
+                Some(equiv) if equiv.eq_ignore_ascii_case("content-type") => {
+                    attr("content").and_then(charset_param)
+                }
+                _ => None,
+            },
+        };
+        if let Some(encoding) = encoding {
+            // a page can't declare itself utf-16 in ascii-compatible bytes,
+            // output_encoding() turns that into utf-8
+            return Some(encoding.output_encoding());
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the text and encoding of body, fed in pieces of size
+    fn decode_in_pieces(content_type: Option<&str>, body: &[u8], size: usize) -> (String, String) {
+        let mut decoder = StreamDecoder::new(content_type);
+        let mut text = String::new();
+        for piece in body.chunks(size.max(1)) {
+            text.push_str(&decoder.feed(piece));
+        }
+        text.push_str(&decoder.finish());
+        (text, decoder.encoding().to_string())
+    }
+
+    fn decode(content_type: Option<&str>, body: &[u8]) -> (String, String) {
+        let whole = decode_in_pieces(content_type, body, body.len());
+        assert_eq!(
+            whole,
+            decode_in_pieces(content_type, body, 1),
+            "a byte at a time"
+        );
+        whole
+    }
+
+    fn encoding(content_type: Option<&str>, body: &[u8]) -> String {
+        decode(content_type, body).1
+    }
+
+    #[test]
+    fn a_bom_comes_first() {
+        let body = b"\xff\xfeh\x00i\x00";
+        let html = Some("text/html; charset=iso-8859-1");
+        assert_eq!(
+            decode(html, body),
+            ("hi".to_string(), "UTF-16LE".to_string())
+        );
+        let body = b"\xef\xbb\xbf<meta charset=koi8-r>\xc3\xa9";
+        assert_eq!(
+            decode(None, body),
+            ("<meta charset=koi8-r>é".to_string(), "UTF-8".to_string())
+        );
+    }
+
+    #[test]
+    fn then_the_content_type() {
+        let body = b"<meta charset=windows-1252>\x93\xfa";
+        assert_eq!(
+            decode(Some("text/html; charset=\"Shift_JIS\""), body),
+            (
+                "<meta charset=windows-1252>日".to_string(),
+                "Shift_JIS".to_string()
+            )
+        );
+        assert_eq!(
+            encoding(Some("text/html;charset=KOI8-R; x=y"), b""),
+            "KOI8-R"
+        );
+        assert_eq!(
+            encoding(Some("text/html; charset='euc-kr'"), b"a"),
+            "EUC-KR"
+        );
+        // a label nobody knows is no declaration
+        assert_eq!(
+            encoding(Some("text/html; charset=klingon"), b"\xe9!"),
+            "windows-1252"
+        );
+    }
+
+    #[test]
+    fn then_a_meta_tag() {
+        let body = b"<!doctype html><head><meta charset=\"iso-8859-2\"></head>\xb1";
+        let (text, label) = decode(Some("text/html"), body);
+        assert_eq!(label, "ISO-8859-2");
+        assert!(text.ends_with('ą'));
+        let body = b"<meta http-equiv=Content-Type content='text/html; charset=koi8-r'>";
+        assert_eq!(encoding(None, body), "KOI8-R");
+        // an http-equiv other than content-type says nothing about it
+        let body = b"<meta http-equiv=refresh content='0; charset=koi8-r'>\xe9!";
+        assert_eq!(encoding(None, body), "windows-1252");
+        // bytes that are ascii can't be utf-16
+        assert_eq!(encoding(None, b"<meta charset=utf-16le>"), "UTF-8");
+    }
+
+    #[test]
+    fn the_prescan_only_looks_so_far() {
+        let mut body = vec![b' '; PRESCAN_LEN];
+        body.extend_from_slice(b"<meta charset=koi8-r>");
+        assert_eq!(encoding(None, &body), "UTF-8");
+    }
+
+    #[test]
+    fn then_whether_it_is_utf8() {
+        assert_eq!(
+            decode(None, "héllo".as_bytes()),
+            ("héllo".to_string(), "UTF-8".to_string())
+        );
+        assert_eq!(
+            decode(None, b"h\xe9llo"),
+            ("héllo".to_string(), "windows-1252".to_string())
+        );
+        // a character cut off by the end of the prescan is still utf-8
+        let mut body = vec![b'a'; PRESCAN_LEN - 1];
+        body.extend_from_slice("é".as_bytes());
+        assert_eq!(encoding(None, &body), "UTF-8");
+    }
+
+    #[test]
+    fn bad_bytes_are_warned_about() {
+        let mut decoder = StreamDecoder::new(Some("text/html; charset=utf-8"));
+        let mut text = decoder.feed(b"a\xffb");
+        text.push_str(&decoder.finish());
+        assert_eq!(text, "a\u{fffd}b");
+        assert!(decoder.warning().is_some());
+        let mut decoder = StreamDecoder::new(None);
+        decoder.feed(b"fine");
+        decoder.finish();
+        assert!(decoder.warning().is_none());
+    }
+}
@@ -0,0 +1,152 @@
+// the : command line. a line is a command and its arguments, as
+// "open example.com". the commands of the keymap run by their names too.
+
+use crate::config;
+use crate::keys::COMMANDS;
+use crate::visits::Visits;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+// lines kept in the command history
+const HISTORY_SIZE: usize = 200;
+
+// the commands that take arguments, with how they are used
+pub const LINE_COMMANDS: &[(&str, &str, &str)] = &[
+    ("open", "open [url]", "go to url"),
+    ("tabnew", "tabnew [url]", "a new tab, going to url"),
+    (
+        "bookmark",
+        "bookmark add [title]",
+        "bookmark this page, as title",
+    ),
+    (
+        "bookmark",
+        "bookmark open query",
+        "go to the bookmark that best matches",
+    ),
+    ("set", "set [name[=value]]", "show or change a setting"),
+    ("save", "save file", "save this page to file"),
+    (
+        "source",
+        "source file",
+        "run the commands in file, one a line",
+    ),
+];
+
+// short names of commands
+const ALIASES: &[(&str, &str)] = &[("o", "open"), ("q", "quit")];
+
+// the command of a line and its arguments
+pub fn parse(line: &str) -> (&str, &str) {
+    let line = line.trim().trim_start_matches(':');
+    let (name, args) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let name = match ALIASES.iter().find(|(alias, _)| *alias == name) {
+        Some((_, name)) => name,
+        None => name,
+    };
+    (name, args.trim())
+}
+
+// ways to finish the line typed so far, each a whole line
+pub fn complete(line: &str, visits: &Visits) -> Vec<String> {
+    let Some((name, arg)) = line.split_once(' ') else {
+        let mut names: Vec<&str> = LINE_COMMANDS.iter().map(|&(name, _, _)| name).collect();
+        names.extend(COMMANDS.iter().map(|&(_, name, _)| name));
+        names.sort();
+        names.dedup();
+        return names
+            .into_iter()
+            .filter(|name| name.starts_with(line))
+            .map(str::to_string)
+            .collect();
+    };
+    let args = match parse(line).0 {
+        "open" | "tabnew" => visits.complete(arg),
+        "bookmark" => ["add", "open"]
+            .iter()
+            .filter(|a| a.starts_with(arg))
+            .map(|a| a.to_string())
+            .collect(),
+        "set" => config::SETTINGS
+            .iter()
+            .filter(|s| s.starts_with(arg))
+            .map(|s| format!("{}=", s))
+            .collect(),
+        "save" | "source" => files(arg),
+        _ => Vec::new(),
+    };
+    args.into_iter()
+        .map(|arg| format!("{} {}", name, arg))
+        .collect()
+}
+
+// the files whose path starts with typed, directories ending in '/'
+fn files(typed: &str) -> Vec<String> {
+    let (dir, start) = match typed.rfind('/') {
+        Some(i) => (&typed[..=i], &typed[i + 1..]),
+        None => ("", typed),
+    };
+    let Ok(entries) = fs::read_dir(if dir.is_empty() { "." } else { dir }) else {
+        return Vec::new();
+    };
+    let mut files: Vec<String> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with(start) || (name.starts_with('.') && !start.starts_with('.')) {
+                return None;
+            }
+            let slash = match entry.path().is_dir() {
+                true => "/",
+                false => "",
+            };
+            Some(format!("{}{}{}", dir, name, slash))
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+// every command that takes arguments, for the help screen
+pub fn help() -> String {
+    let mut buf = String::from("commands, after :\n\n");
+    for (_, usage, about) in LINE_COMMANDS {
+        buf.push_str(&format!("{:<32} {}\n", usage, about));
+    }
+    buf.push_str("and every command above by its name, as :history\n");
+    buf
+}
+
+// the lines run before, kept in the config dir, one a line
+pub struct History {
+    file: Option<PathBuf>,
+    pub lines: Vec<String>,
+}
+
+impl History {
+    pub fn load(file: Option<PathBuf>) -> History {
+        let lines = file
+            .as_ref()
+            .and_then(|f| fs::read_to_string(f).ok())
+            .map(|text| text.lines().map(str::to_string).collect())
+            .unwrap_or_default();
+        History { file, lines }
+    }
+
+    // a line run again moves to the end
+    pub fn add(&mut self, line: &str) -> io::Result<()> {
+        self.lines.retain(|l| l != line);
+        self.lines.push(line.to_string());
+        if self.lines.len() > HISTORY_SIZE {
+            self.lines.drain(..self.lines.len() - HISTORY_SIZE);
+        }
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(file, self.lines.join("\n") + "\n")
+    }
+}
@@ -0,0 +1,347 @@
+// where ibrow keeps its files, and the settings in config.toml there
+
+use crate::keys::{self, Binding, Command};
+use crate::url::{self, Url};
+use crossterm::style::Color;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use toml::{Table, Value};
+
+const USER_AGENT: &str = "ibrow/0.1.0";
+
+// $XDG_CONFIG_HOME/ibrow, or ~/.config/ibrow
+pub fn dir() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => home::home_dir()?.join(".config"),
+    };
+    Some(base.join("ibrow"))
+}
+
+// colors of what the page and the status line show, none leaves them as
+// the terminal has them
+#[derive(Clone, Copy, Default)]
+pub struct Theme {
+    pub link: Option<Color>,
+    pub field: Option<Color>,
+    // the background of link labels, reversed when none
+    pub hint: Option<Color>,
+    pub status: Option<Color>,
+}
+
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+pub fn theme() -> &'static Theme {
+    THEME.get_or_init(Theme::default)
+}
+
+pub fn set_theme(theme: Theme) {
+    let _ = THEME.set(theme);
+}
+
+pub struct Config {
+    pub user_agent: String,
+    pub home_page: Option<Url>,
+    pub download_dir: Option<PathBuf>,
+    // clicks, the wheel and selecting text go to ibrow, not the terminal
+    pub mouse: bool,
+    // "native", "curl" or "mock:<dir>"
+    pub fetcher: String,
+    // an http proxy every request goes through
+    pub proxy: Option<Url>,
+    // sent with every request
+    pub headers: Vec<(String, String)>,
+    pub theme: Theme,
+    // bindings over the default keymap, none unbinds the keys
+    pub keys: Vec<Binding>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            user_agent: USER_AGENT.to_string(),
+            home_page: None,
+            download_dir: None,
+            mouse: true,
+            fetcher: "native".to_string(),
+            proxy: None,
+            headers: Vec::new(),
+            theme: Theme::default(),
+            keys: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    // reads config.toml, a missing one is the same as an empty one. every
+    // mistake in it is reported, not only the first.
+    pub fn load() -> Result<Config, Vec<String>> {
+        let Some(path) = dir().map(|dir| dir.join("config.toml")) else {
+            return Ok(Config::default());
+        };
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => return Err(vec![format!("{}: {}", path.display(), e)]),
+        };
+        Config::parse(&text).map_err(|errors| {
+            errors
+                .into_iter()
+                .map(|e| format!("{}: {}", path.display(), e))
+                .collect()
+        })
+    }
+
+    fn parse(text: &str) -> Result<Config, Vec<String>> {
+        let table: Table = text.parse().map_err(|e: toml::de::Error| {
+            let line = e
+                .span()
+                .map_or(1, |span| text[..span.start].matches('\n').count() + 1);
+            vec![format!("line {}: {}", line, e.message().trim_end())]
+        })?;
+        let mut config = Config::default();
+        let mut errors = Vec::new();
+        for (name, value) in &table {
+            if let Err(e) = config.setting(name, value, &mut errors) {
+                errors.push(e);
+            }
+        }
+        match errors.is_empty() {
+            true => Ok(config),
+            false => Err(errors),
+        }
+    }
+
+    fn setting(
+        &mut self,
+        name: &str,
+        value: &Value,
+        errors: &mut Vec<String>,
+    ) -> Result<(), String> {
+        match name {
+            "user_agent" => string(name, value).map(|s| self.user_agent = s),
+            "home_page" => string(name, value)
+                .and_then(|s| url::parse_input(&s).map_err(|e| format!("{}: {}", name, e)))
+                .map(|url| self.home_page = Some(url)),
+            "download_dir" => string(name, value)
+                .and_then(|s| download_dir(&s))
+                .map(|dir| self.download_dir = Some(dir)),
+            "mouse" => boolean(name, value).map(|b| self.mouse = b),
+            "fetcher" => string(name, value)
+                .and_then(|s| fetcher(&s))
+                .map(|s| self.fetcher = s),
+            "proxy" => string(name, value)
+                .and_then(|s| proxy(&s))
+                .map(|url| self.proxy = Some(url)),
+            "headers" => headers(value, errors).map(|h| self.headers = h),
+            "theme" => theme_table(value, errors).map(|t| self.theme = t),
+            "keys" => keys(value, errors).map(|k| self.keys = k),
+            _ => Err(format!("unknown setting {}", name)),
+        }
+    }
+
+    // changes a setting while ibrow runs. the underscores of the name may
+    // be left out, an empty value unsets what may be unset.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<&'static str, String> {
+        let name = setting_name(name)?;
+        match (name, value.is_empty()) {
+            ("home_page", true) => self.home_page = None,
+            ("download_dir", true) => self.download_dir = None,
+            ("proxy", true) => self.proxy = None,
+            _ => self.setting(name, &Value::String(value.to_string()), &mut Vec::new())?,
+        }
+        Ok(name)
+    }
+
+    pub fn get(&self, name: &str) -> Result<String, String> {
+        let value = match setting_name(name)? {
+            "user_agent" => self.user_agent.clone(),
+            "home_page" => self
+                .home_page
+                .as_ref()
+                .map(Url::to_string)
+                .unwrap_or_default(),
+            "download_dir" => self
+                .download_dir
+                .as_ref()
+                .map(|dir| dir.display().to_string())
+                .unwrap_or_default(),
+            "fetcher" => self.fetcher.clone(),
+            _ => self.proxy.as_ref().map(Url::to_string).unwrap_or_default(),
+        };
+        Ok(value)
+    }
+}
+
+// the settings that can be set and shown as text, the rest are tables
+pub const SETTINGS: &[&str] = &[
+    "user_agent",
+    "home_page",
+    "download_dir",
+    "fetcher",
+    "proxy",
+];
+
+fn setting_name(name: &str) -> Result<&'static str, String> {
+    SETTINGS
+        .iter()
+        .find(|s| s.replace('_', "") == name.replace('_', ""))
+        .copied()
+        .ok_or(format!("unknown setting {}", name))
+}
+
+fn string(name: &str, value: &Value) -> Result<String, String> {
+    match value {
+        Value::String(s) => Ok(s.clone()),
+        _ => Err(format!("{} should be a string", name)),
+    }
+}
+
+fn boolean(name: &str, value: &Value) -> Result<bool, String> {
+    match value {
+        Value::Boolean(b) => Ok(*b),
+        _ => Err(format!("{} should be true or false", name)),
+    }
+}
+
+fn table<'a>(name: &str, value: &'a Value) -> Result<&'a Table, String> {
+    match value {
+        Value::Table(t) => Ok(t),
+        _ => Err(format!("{} should be a table, as [{}]", name, name)),
+    }
+}
+
+fn download_dir(s: &str) -> Result<PathBuf, String> {
+    let dir = match s.strip_prefix("~/") {
+        Some(rest) => match home::home_dir() {
+            Some(home) => home.join(rest),
+            None => return Err("download_dir: no home directory for ~".to_string()),
+        },
+        None => PathBuf::from(s),
+    };
+    match dir.is_dir() {
+        true => Ok(dir),
+        false => Err(format!(
+            "download_dir: {} is not a directory",
+            dir.display()
+        )),
+    }
+}
+
+fn fetcher(s: &str) -> Result<String, String> {
+    match s {
+        "native" | "curl" => Ok(s.to_string()),
+        _ if s.starts_with("mock:") => Ok(s.to_string()),
+        _ => Err(format!(
+            "fetcher: {} is none of native, curl or mock:<dir>",
+            s
+        )),
+    }
+}
+
+fn proxy(s: &str) -> Result<Url, String> {
+    let url = url::parse_input(s).map_err(|e| format!("proxy: {}", e))?;
+    match (url.scheme(), url.host()) {
+        ("http", Some(host)) if !host.is_empty() => Ok(url),
+        _ => Err(format!("proxy: {} should be http://host:port", s)),
+    }
+}
+
+fn headers(value: &Value, errors: &mut Vec<String>) -> Result<Vec<(String, String)>, String> {
+    let mut headers = Vec::new();
+    for (name, value) in table("headers", value)? {
+        let is_token = !name.is_empty()
+            && name
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b));
+        let value = match string(&format!("headers.{}", name), value) {
+            Ok(value) => value,
+            Err(e) => {
+                errors.push(e);
+                continue;
+            }
+        };
+        if !is_token {
+            errors.push(format!("headers: {} is not a header name", name));
+        } else if value.contains(['\r', '\n']) {
+            errors.push(format!("headers.{}: a value can't have line breaks", name));
+        } else {
+            headers.push((name.clone(), value));
+        }
+    }
+    Ok(headers)
+}
+
+fn theme_table(value: &Value, errors: &mut Vec<String>) -> Result<Theme, String> {
+    let mut theme = Theme::default();
+    for (name, value) in table("theme", value)? {
+        let color = string(&format!("theme.{}", name), value)
+            .and_then(|s| color(&s).map_err(|e| format!("theme.{}: {}", name, e)));
+        let color = match color {
+            Ok(color) => Some(color),
+            Err(e) => {
+                errors.push(e);
+                continue;
+            }
+        };
+        match name.as_str() {
+            "link" => theme.link = color,
+            "field" => theme.field = color,
+            "hint" => theme.hint = color,
+            "status" => theme.status = color,
+            _ => errors.push(format!(
+                "theme: {} is none of link, field, hint or status",
+                name
+            )),
+        }
+    }
+    Ok(theme)
+}
+
+// a color by name, like "dark_blue", by number in the 256 color palette
+// or as "#rrggbb"
+fn color(s: &str) -> Result<Color, String> {
+    if let Ok(color) = Color::try_from(s) {
+        return Ok(color);
+    }
+    if let Ok(n) = s.parse::<u8>() {
+        return Ok(Color::AnsiValue(n));
+    }
+    let hex = s
+        .strip_prefix('#')
+        .filter(|h| h.len() == 6)
+        .and_then(|h| u32::from_str_radix(h, 16).ok());
+    match hex {
+        Some(rgb) => Ok(Color::Rgb {
+            r: (rgb >> 16) as u8,
+            g: (rgb >> 8) as u8,
+            b: rgb as u8,
+        }),
+        None => Err(format!("{} is not a color", s)),
+    }
+}
+
+// a key sequence and the name of the command it runs, or "none"
+fn keys(value: &Value, errors: &mut Vec<String>) -> Result<Vec<Binding>, String> {
+    let mut bindings = Vec::new();
+    for (seq, value) in table("keys", value)? {
+        let Some(keys) = keys::parse_keys(seq) else {
+            errors.push(format!("keys: {} is not a key", seq));
+            continue;
+        };
+        let command = string(&format!("keys.{}", seq), value).and_then(|s| match s.as_str() {
+            "none" => Ok(None),
+            _ => match Command::from_name(&s) {
+                Some(command) => Ok(Some(command)),
+                None => Err(format!("keys.{}: {} is not a command", seq, s)),
+            },
+        });
+        match command {
+            Ok(command) => bindings.push((keys, command)),
+            Err(e) => errors.push(e),
+        }
+    }
+    Ok(bindings)
+}
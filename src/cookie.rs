@@ -0,0 +1,684 @@
+// cookies, as rfc 6265 has them: parsing set-cookie (5.2), the storage
+// model (5.3) and building the cookie header (5.4). the jar is saved in the
+// netscape cookies.txt format that curl and wget read too.
+
+use crate::url::Url;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+// one jar is shared by the ui and the fetch thread
+pub type SharedJar = Arc<Mutex<Jar>>;
+
+#[derive(Clone)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    pub domain: String,
+    // only sent to the exact host that set it, there was no Domain attribute
+    pub host_only: bool,
+    pub path: String,
+    // unix time, None for a session cookie
+    pub expires: Option<i64>,
+    pub secure: bool,
+    pub http_only: bool,
+    creation: i64,
+}
+
+impl Cookie {
+    // the set-cookie-string algorithm, without the storage checks
+    fn parse(header: &str) -> Option<Cookie> {
+        let mut parts = header.split(';');
+        let (name, value) = parts.next()?.split_once('=')?;
+        let name = name.trim();
+        if name.is_empty() {
+            return None;
+        }
+        let now = now();
+        let mut cookie = Cookie {
+            name: name.to_string(),
+            value: value.trim().to_string(),
+            domain: String::new(),
+            host_only: true,
+            path: String::new(),
+            expires: None,
+            secure: false,
+            http_only: false,
+            creation: now,
+        };
+        let mut max_age = None;
+        let mut expires = None;
+        for attr in parts {
+            let (attr, value) = attr.split_once('=').unwrap_or((attr, ""));
+            let value = value.trim();
+            match attr.trim().to_ascii_lowercase().as_str() {
+                "expires" => expires = parse_date(value).or(expires),
+                "max-age" => {
+                    let valid = value.starts_with(|c: char| c.is_ascii_digit() || c == '-');
+                    if let Some(delta) = value.parse::<i64>().ok().filter(|_| valid) {
+                        max_age = Some(match delta {
+                            ..=0 => i64::MIN,
+                            delta => now.saturating_add(delta),
+                        });
+                    }
+                }
+                "domain" if !value.is_empty() => {
+                    let domain = value.strip_prefix('.').unwrap_or(value);
+                    cookie.domain = domain.to_ascii_lowercase();
+                }
+                "path" => cookie.path = value.to_string(),
+                "secure" => cookie.secure = true,
+                "httponly" => cookie.http_only = true,
+                _ => (),
+            }
+        }
+        // max-age wins over expires
+        cookie.expires = max_age.or(expires);
+        Some(cookie)
+    }
+
+    fn is_expired(&self, now: i64) -> bool {
+        self.expires.is_some_and(|t| t <= now)
+    }
+
+    fn same_as(&self, other: &Cookie) -> bool {
+        self.name == other.name && self.domain == other.domain && self.path == other.path
+    }
+}
+
+pub struct Jar {
+    cookies: Vec<Cookie>,
+    file: Option<PathBuf>,
+    changed: bool,
+}
+
+impl Jar {
+    // a jar that can't be read starts out empty
+    pub fn load(file: Option<PathBuf>) -> Jar {
+        let mut jar = Jar {
+            cookies: Vec::new(),
+            file,
+            changed: false,
+        };
+        if let Some(text) = jar.file.as_ref().and_then(|f| fs::read_to_string(f).ok()) {
+            let now = now();
+            jar.cookies = text
+                .lines()
+                .filter_map(parse_line)
+                .filter(|c| !c.is_expired(now))
+                .collect();
+        }
+        jar
+    }
+
+    pub fn shared(self) -> SharedJar {
+        Arc::new(Mutex::new(self))
+    }
+
+    // session cookies are not kept across runs
+    pub fn save(&mut self) -> io::Result<()> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        if !self.changed {
+            return Ok(());
+        }
+        let now = now();
+        let mut out = String::from("# Netscape HTTP Cookie File\n");
+        for c in &self.cookies {
+            let Some(expires) = c.expires.filter(|&t| t > now) else {
+                continue;
+            };
+            let flag = |b: bool| if b { "TRUE" } else { "FALSE" };
+            writeln!(
+                out,
+                "{}{}{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                if c.http_only { "#HttpOnly_" } else { "" },
+                if c.host_only { "" } else { "." },
+                c.domain,
+                flag(!c.host_only),
+                c.path,
+                flag(c.secure),
+                expires,
+                c.name,
+                c.value
+            )
+            .unwrap();
+        }
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        // the file holds login sessions, keep it to ourselves
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options.open(file)?.write_all(out.as_bytes())?;
+        self.changed = false;
+        Ok(())
+    }
+
+    // takes in a set-cookie header of a response from url
+    pub fn store(&mut self, url: &Url, header: &str) {
+        let Some(mut cookie) = Cookie::parse(header) else {
+            return;
+        };
+        let Some(host) = url.host().map(str::to_ascii_lowercase) else {
+            return;
+        };
+        if cookie.domain.is_empty() {
+            cookie.domain = host;
+        } else if cookie.domain != host
+            && (!domain_match(&host, &cookie.domain) || is_public_suffix(&cookie.domain))
+        {
+            return;
+        } else {
+            cookie.host_only = false;
+        }
+        if !cookie.path.starts_with('/') {
+            cookie.path = default_path(url.path()).to_string();
+        }
+        let secure_url = url.scheme() == "https";
+        if cookie.secure && !secure_url {
+            return;
+        }
+
+        let now = now();
+        if let Some(old) = self.cookies.iter().position(|c| c.same_as(&cookie)) {
+            let old = self.cookies.remove(old);
+            // a plain http response can't replace a secure cookie
+            if old.secure && !secure_url {
+                self.cookies.push(old);
+                return;
+            }
+            cookie.creation = old.creation;
+        }
+        if !cookie.is_expired(now) {
+            self.cookies.push(cookie);
+        }
+        self.changed = true;
+    }
+
+    // the cookie header for a request to url, if there is anything to send
+    pub fn header(&self, url: &Url) -> Option<String> {
+        let host = url.host()?.to_ascii_lowercase();
+        let now = now();
+        let mut matching: Vec<&Cookie> = self
+            .cookies
+            .iter()
+            .filter(|c| match c.host_only {
+                true => c.domain == host,
+                false => domain_match(&host, &c.domain),
+            })
+            .filter(|c| path_match(url.path(), &c.path))
+            .filter(|c| !c.secure || url.scheme() == "https")
+            .filter(|c| !c.is_expired(now))
+            .collect();
+        if matching.is_empty() {
+            return None;
+        }
+        // longer paths first, then older cookies first
+        matching.sort_by(|a, b| {
+            b.path
+                .len()
+                .cmp(&a.path.len())
+                .then(a.creation.cmp(&b.creation))
+        });
+        let pairs: Vec<String> = matching
+            .iter()
+            .map(|c| format!("{}={}", c.name, c.value))
+            .collect();
+        Some(pairs.join("; "))
+    }
+
+    // the live cookies, grouped by site
+    pub fn list(&self) -> Vec<&Cookie> {
+        let now = now();
+        let mut cookies: Vec<&Cookie> =
+            self.cookies.iter().filter(|c| !c.is_expired(now)).collect();
+        cookies.sort_by(|a, b| a.domain.cmp(&b.domain).then(a.name.cmp(&b.name)));
+        cookies
+    }
+
+    // removes the cookies of a site and its subdomains, or only the one
+    // with that name. returns how many went.
+    pub fn remove(&mut self, site: &str, name: Option<&str>) -> usize {
+        let site = site.trim_start_matches('.').to_ascii_lowercase();
+        let before = self.cookies.len();
+        self.cookies.retain(|c| {
+            let on_site = domain_match(&c.domain, &site);
+            !(on_site && name.is_none_or(|n| n == c.name))
+        });
+        let removed = before - self.cookies.len();
+        self.changed |= removed > 0;
+        removed
+    }
+}
+
+// a line of a cookies.txt file
+fn parse_line(line: &str) -> Option<Cookie> {
+    let (http_only, line) = match line.strip_prefix("#HttpOnly_") {
+        Some(rest) => (true, rest),
+        None if line.starts_with('#') => return None,
+        None => (false, line),
+    };
+    let fields: Vec<&str> = line.split('\t').collect();
+    let [domain, _, path, secure, expires, name, value] = fields[..] else {
+        return None;
+    };
+    if name.is_empty() {
+        return None;
+    }
+    let host_only = !domain.starts_with('.');
+    Some(Cookie {
+        name: name.to_string(),
+        value: value.to_string(),
+        domain: domain.trim_start_matches('.').to_string(),
+        host_only,
+        path: path.to_string(),
+        expires: expires.parse().ok().filter(|&t| t != 0),
+        secure: secure == "TRUE",
+        http_only,
+        creation: now(),
+    })
+}
+
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+// 5.1.3, the host is the domain or a subdomain of it, and not an ip address
+fn domain_match(host: &str, domain: &str) -> bool {
+    if host == domain {
+        return true;
+    }
+    let ip = host.starts_with('[') || host.parse::<std::net::Ipv4Addr>().is_ok();
+    !ip && host.len() > domain.len()
+        && host.ends_with(domain)
+        && host.as_bytes()[host.len() - domain.len() - 1] == b'.'
+}
+
+// second level labels that countries hand out under, as in co.uk
+const SHARED_SECOND_LEVEL: &[&str] = &[
+    "ac", "co", "com", "edu", "go", "gob", "gov", "govt", "ltd", "mil", "ne", "net", "nic", "or",
+    "org", "plc", "sch",
+];
+
+// a domain that many unrelated sites sit under, which no cookie may be set
+// for. without the public suffix list this only knows top level domains
+// and the usual shared second level ones of country domains, like co.uk
+// or com.au; github.io and the like still get through.
+fn is_public_suffix(domain: &str) -> bool {
+    match domain.split('.').collect::<Vec<_>>()[..] {
+        [_] => true,
+        [second, top] => {
+            top.len() == 2
+                && top.bytes().all(|b| b.is_ascii_alphabetic())
+                && SHARED_SECOND_LEVEL.contains(&second)
+        }
+        _ => false,
+    }
+}
+
+// 5.1.4
+fn path_match(path: &str, cookie_path: &str) -> bool {
+    match path.strip_prefix(cookie_path) {
+        Some(rest) => cookie_path.ends_with('/') || rest.is_empty() || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+fn default_path(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(0) | None => "/",
+        Some(i) => &path[..i],
+    }
+}
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+// 5.1.1, the forgiving cookie date format
+fn parse_date(s: &str) -> Option<i64> {
+    let delimiter = |c: char| {
+        c == '\t'
+            || (' '..='/').contains(&c)
+            || (';'..='@').contains(&c)
+            || ('['..='`').contains(&c)
+            || ('{'..='~').contains(&c)
+    };
+    let (mut time, mut day, mut month, mut year) = (None, None, None, None);
+    for token in s.split(delimiter).filter(|t| !t.is_empty()) {
+        if time.is_none() {
+            if let Some(t) = parse_time(token) {
+                time = Some(t);
+                continue;
+            }
+        }
+        if day.is_none() {
+            if let Some(d) = leading_digits(token, 1, 2) {
+                day = Some(d);
+                continue;
+            }
+        }
+        if month.is_none() {
+            let prefix = token.get(..3).unwrap_or_default();
+            if let Some(m) = MONTHS.iter().position(|m| m.eq_ignore_ascii_case(prefix)) {
+                month = Some(m as i64 + 1);
+                continue;
+            }
+        }
+        if year.is_none() {
+            if let Some(y) = leading_digits(token, 2, 4) {
+                year = Some(y);
+            }
+        }
+    }
+    let (h, m, sec) = time?;
+    let (day, month, mut year) = (day?, month?, year?);
+    year += match year {
+        70..=99 => 1900,
+        0..=69 => 2000,
+        _ => 0,
+    };
+    if !(1..=31).contains(&day) || year < 1601 || h > 23 || m > 59 || sec > 59 {
+        return None;
+    }
+    Some(days_from_civil(year, month, day) * 86400 + h * 3600 + m * 60 + sec)
+}
+
+// hh:mm:ss, anything may follow the seconds
+fn parse_time(token: &str) -> Option<(i64, i64, i64)> {
+    let mut parts = token.splitn(3, ':');
+    let h = parts.next()?;
+    let m = parts.next()?;
+    let s = parts.next()?;
+    let whole = |p: &str| (1..=2).contains(&p.len()) && p.bytes().all(|b| b.is_ascii_digit());
+    if !whole(h) || !whole(m) {
+        return None;
+    }
+    Some((h.parse().ok()?, m.parse().ok()?, leading_digits(s, 1, 2)?))
+}
+
+// min to max digits, followed by anything but a digit
+fn leading_digits(token: &str, min: usize, max: usize) -> Option<i64> {
+    let len = token.bytes().take_while(u8::is_ascii_digit).count();
+    match (min..=max).contains(&len) {
+        true => token[..len].parse().ok(),
+        false => None,
+    }
+}
+
+// days since 1970-01-01 of a date in the proleptic gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+// year, month and day of a unix time
+pub fn civil_from_unix(time: i64) -> (i64, i64, i64) {
+    let z = time.div_euclid(86400) + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    // a jar with each (url, set-cookie) stored in it
+    fn jar(set: &[(&str, &str)]) -> Jar {
+        let mut jar = Jar::load(None);
+        for (at, header) in set {
+            jar.store(&url(at), header);
+        }
+        jar
+    }
+
+    fn header(jar: &Jar, at: &str) -> Option<String> {
+        jar.header(&url(at))
+    }
+
+    #[test]
+    fn domain_and_path_match() {
+        assert!(domain_match("example.com", "example.com"));
+        assert!(domain_match("a.b.example.com", "example.com"));
+        assert!(!domain_match("badexample.com", "example.com"));
+        assert!(!domain_match("example.com", "a.example.com"));
+        assert!(!domain_match("1.2.3.4", "2.3.4"));
+
+        assert!(path_match("/", "/"));
+        assert!(path_match("/a/b", "/a"));
+        assert!(path_match("/a/b", "/a/"));
+        assert!(path_match("/a", "/a"));
+        assert!(!path_match("/ab", "/a"));
+        assert!(!path_match("/", "/a"));
+        assert_eq!(default_path("/a/b/c"), "/a/b");
+        assert_eq!(default_path("/a"), "/");
+        assert_eq!(default_path(""), "/");
+    }
+
+    #[test]
+    fn host_only_without_a_domain() {
+        let jar = jar(&[("http://www.example.com/", "a=1")]);
+        assert_eq!(
+            header(&jar, "http://www.example.com/x").as_deref(),
+            Some("a=1")
+        );
+        assert_eq!(header(&jar, "http://sub.www.example.com/"), None);
+        assert_eq!(header(&jar, "http://example.com/"), None);
+    }
+
+    #[test]
+    fn a_domain_takes_in_subdomains() {
+        let jar = jar(&[("http://www.example.com/", "a=1; Domain=.Example.COM")]);
+        assert_eq!(header(&jar, "http://example.com/").as_deref(), Some("a=1"));
+        assert_eq!(
+            header(&jar, "http://x.y.example.com/").as_deref(),
+            Some("a=1")
+        );
+        assert_eq!(header(&jar, "http://example.org/"), None);
+    }
+
+    #[test]
+    fn a_domain_must_cover_the_host() {
+        let jar = jar(&[
+            ("http://www.example.com/", "a=1; Domain=other.com"),
+            ("http://www.example.com/", "b=1; Domain=sub.www.example.com"),
+            ("http://1.2.3.4/", "c=1; Domain=2.3.4"),
+        ]);
+        assert!(jar.list().is_empty());
+    }
+
+    #[test]
+    fn no_cookies_for_public_suffixes() {
+        let refused = jar(&[
+            ("http://example.com/", "a=1; Domain=com"),
+            ("http://www.example.co.uk/", "b=1; Domain=co.uk"),
+            ("http://shop.example.com.au/", "c=1; Domain=com.au"),
+        ]);
+        assert!(refused.list().is_empty());
+        let jar = jar(&[
+            ("http://www.example.co.uk/", "a=1; Domain=example.co.uk"),
+            ("http://me.github.io/", "b=1; Domain=github.io"),
+        ]);
+        assert_eq!(
+            header(&jar, "http://example.co.uk/").as_deref(),
+            Some("a=1")
+        );
+        // the limit of doing without the list: this is shared, but let through
+        assert_eq!(
+            header(&jar, "http://someone-else.github.io/").as_deref(),
+            Some("b=1")
+        );
+    }
+
+    #[test]
+    fn secure_cookies_need_https() {
+        let jar = jar(&[
+            ("http://example.com/", "plain=1; Secure"),
+            ("https://example.com/", "s=1; Secure"),
+            // nor can http replace a secure one
+            ("http://example.com/", "s=2"),
+        ]);
+        assert_eq!(header(&jar, "http://example.com/"), None);
+        assert_eq!(header(&jar, "https://example.com/").as_deref(), Some("s=1"));
+    }
+
+    #[test]
+    fn max_age_wins_over_expires() {
+        let later = "Expires=Fri, 01 Jan 2100 00:00:00 GMT";
+        let jar = jar(&[
+            ("http://example.com/", &format!("a=1; Max-Age=0; {}", later)),
+            (
+                "http://example.com/",
+                &format!("b=1; {}; Max-Age=60", later),
+            ),
+            (
+                "http://example.com/",
+                "c=1; Expires=Thu, 01 Jan 1970 00:00:01 GMT; Max-Age=60",
+            ),
+            // a bad max-age is no max-age
+            (
+                "http://example.com/",
+                "d=1; Max-Age=1e9; Expires=Thu, 01 Jan 1970 00:00:01 GMT",
+            ),
+        ]);
+        let names: Vec<&str> = jar.list().iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["b", "c"]);
+        let b = jar.list()[0].expires.unwrap();
+        assert!((b - now() - 60).abs() <= 1);
+    }
+
+    #[test]
+    fn an_expired_cookie_deletes_the_one_it_replaces() {
+        let jar = jar(&[
+            ("http://example.com/", "a=1; Max-Age=100"),
+            (
+                "http://example.com/",
+                "a=; Expires=Thu, 01 Jan 1970 00:00:00 GMT",
+            ),
+        ]);
+        assert!(jar.list().is_empty());
+    }
+
+    #[test]
+    fn longer_paths_go_first() {
+        let jar = jar(&[
+            ("http://example.com/", "a=1"),
+            ("http://example.com/", "b=2; Path=/docs"),
+            ("http://example.com/docs/x", "c=3"),
+        ]);
+        assert_eq!(
+            header(&jar, "http://example.com/docs/page").as_deref(),
+            Some("b=2; c=3; a=1")
+        );
+        assert_eq!(
+            header(&jar, "http://example.com/other").as_deref(),
+            Some("a=1")
+        );
+        assert_eq!(
+            header(&jar, "http://example.com/docsx").as_deref(),
+            Some("a=1")
+        );
+    }
+
+    #[test]
+    fn dates() {
+        let t = 784111777;
+        assert_eq!(parse_date("Sun, 06 Nov 1994 08:49:37 GMT"), Some(t));
+        assert_eq!(parse_date("Sunday, 06-Nov-94 08:49:37 GMT"), Some(t));
+        assert_eq!(parse_date("Sun Nov  6 08:49:37 1994"), Some(t));
+        assert_eq!(parse_date("6 november 1994 8:49:37"), Some(t));
+        assert_eq!(
+            parse_date("Thu, 01 Jan 2065 00:00:00 GMT"),
+            parse_date("1 jan 65 0:0:0")
+        );
+        assert_eq!(parse_date("Thu, 01 Jan 1970 00:00:00 GMT"), Some(0));
+        assert_eq!(parse_date("Sun, 06 Nov 1994"), None);
+        assert_eq!(parse_date("Sun, 32 Nov 1994 08:49:37 GMT"), None);
+        assert_eq!(parse_date("Sun, 06 Nov 1600 08:49:37 GMT"), None);
+        assert_eq!(parse_date("Sun, 06 Nov 1994 24:00:00 GMT"), None);
+        assert_eq!(parse_date("whenever"), None);
+        assert_eq!(civil_from_unix(t), (1994, 11, 6));
+    }
+
+    #[test]
+    fn saves_and_loads_cookies_txt() {
+        let file = std::env::temp_dir().join(format!("ibrow-cookies-{}", std::process::id()));
+        let mut jar = jar(&[
+            ("https://www.example.com/a/b", "host=1; Max-Age=1000"),
+            (
+                "https://www.example.com/",
+                "dom=2; Domain=example.com; Path=/; Max-Age=1000",
+            ),
+            (
+                "https://www.example.com/",
+                "sec=3; Secure; HttpOnly; Max-Age=1000",
+            ),
+            ("https://www.example.com/", "session=4"),
+        ]);
+        jar.file = Some(file.clone());
+        jar.save().unwrap();
+        let text = fs::read_to_string(&file).unwrap();
+        assert!(text.contains("\n#HttpOnly_www.example.com\tFALSE\t/\tTRUE\t"));
+        assert!(text.contains("\n.example.com\tTRUE\t/\tFALSE\t"));
+
+        let loaded = Jar::load(Some(file.clone()));
+        fs::remove_file(&file).unwrap();
+        let listed = |jar: &Jar| -> Vec<String> {
+            jar.list()
+                .iter()
+                .map(|c| {
+                    let flags = (c.host_only, c.secure, c.http_only, c.expires);
+                    format!("{} {} {} {:?}", c.name, c.domain, c.path, flags)
+                })
+                .collect()
+        };
+        let mut kept = listed(&jar);
+        kept.retain(|c| !c.starts_with("session "));
+        assert_eq!(listed(&loaded), kept);
+        assert_eq!(
+            header(&loaded, "https://www.example.com/a/x").as_deref(),
+            Some("host=1; dom=2; sec=3")
+        );
+        assert_eq!(
+            header(&loaded, "http://other.example.com/").as_deref(),
+            Some("dom=2")
+        );
+    }
+
+    #[test]
+    fn removes_a_site() {
+        let mut jar = jar(&[
+            ("http://example.com/", "a=1"),
+            ("http://www.example.com/", "b=1"),
+            ("http://example.org/", "c=1"),
+        ]);
+        assert_eq!(jar.remove("www.example.com", Some("a")), 0);
+        assert_eq!(jar.remove(".example.com", None), 2);
+        assert_eq!(jar.list().len(), 1);
+    }
+}
@@ -0,0 +1,486 @@
+// tree construction. a trimmed down version of the spec's insertion modes:
+// we keep the stack of open elements, implied end tags and raw text
+// switching, but don't bother with the head/body/table insertion modes.
+
+use crate::tokenizer::{State, Tag, Token, Tokenizer};
+
+#[derive(Clone)]
+pub struct Dom {
+    pub nodes: Vec<Node>,
+}
+
+#[derive(Clone)]
+pub struct Node {
+    pub children: Vec<usize>,
+    pub data: NodeData,
+}
+
+#[derive(Clone)]
+pub enum NodeData {
+    Document,
+    Doctype,
+    Element(Element),
+    Text(String),
+    Comment,
+}
+
+#[derive(Clone)]
+pub struct Element {
+    pub name: String,
+    pub attrs: Vec<(String, String)>,
+}
+
+impl Element {
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+impl Dom {
+    pub const ROOT: usize = 0;
+
+    pub fn new() -> Dom {
+        Dom {
+            nodes: vec![Node {
+                children: Vec::new(),
+                data: NodeData::Document,
+            }],
+        }
+    }
+
+    pub fn element(&self, id: usize) -> Option<&Element> {
+        match &self.nodes[id].data {
+            NodeData::Element(e) => Some(e),
+            _ => None,
+        }
+    }
+
+    // all the text under a node
+    pub fn text(&self, id: usize) -> String {
+        let mut text = String::new();
+        self.collect_text(id, &mut text);
+        text
+    }
+
+    fn collect_text(&self, id: usize, text: &mut String) {
+        match &self.nodes[id].data {
+            NodeData::Text(t) => text.push_str(t),
+            _ => {
+                for &child in &self.nodes[id].children {
+                    self.collect_text(child, text);
+                }
+            }
+        }
+    }
+
+    fn append(&mut self, parent: usize, data: NodeData) -> usize {
+        let id = self.nodes.len();
+        self.nodes.push(Node {
+            children: Vec::new(),
+            data,
+        });
+        self.nodes[parent].children.push(id);
+        id
+    }
+}
+
+const VOID: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "keygen", "link", "meta", "param",
+    "source", "track", "wbr",
+];
+
+// start tags that close an open <p>
+const CLOSES_P: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "center",
+    "details",
+    "dialog",
+    "dir",
+    "div",
+    "dl",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hgroup",
+    "hr",
+    "li",
+    "listing",
+    "main",
+    "menu",
+    "nav",
+    "ol",
+    "p",
+    "plaintext",
+    "pre",
+    "search",
+    "section",
+    "summary",
+    "table",
+    "ul",
+    "xmp",
+];
+
+const SCOPE: &[&str] = &[
+    "applet", "caption", "html", "table", "td", "th", "marquee", "object", "template",
+];
+
+const HEADINGS: &[&str] = &["h1", "h2", "h3", "h4", "h5", "h6"];
+
+// how deep elements nest. the tree is walked recursively, so past this an
+// element goes next to the current one instead of in it, as browsers do.
+const MAX_DEPTH: usize = 512;
+
+pub struct TreeBuilder {
+    tokenizer: Tokenizer,
+    dom: Dom,
+    stack: Vec<usize>,
+}
+
+impl TreeBuilder {
+    pub fn new() -> TreeBuilder {
+        TreeBuilder {
+            tokenizer: Tokenizer::new(),
+            dom: Dom::new(),
+            stack: vec![Dom::ROOT],
+        }
+    }
+
+    pub fn feed(&mut self, s: &str) {
+        self.tokenizer.feed(s);
+        self.run();
+    }
+
+    // the tree as far as it has been built
+    pub fn dom(&self) -> &Dom {
+        &self.dom
+    }
+
+    pub fn finish(mut self) -> Dom {
+        self.tokenizer.end();
+        self.run();
+        self.dom
+    }
+
+    fn run(&mut self) {
+        while let Some(token) = self.tokenizer.next_token() {
+            self.process(token);
+        }
+    }
+
+    fn current(&self) -> usize {
+        *self.stack.last().unwrap()
+    }
+
+    fn current_name(&self) -> Option<&str> {
+        self.dom.element(self.current()).map(|e| e.name.as_str())
+    }
+
+    // position in the stack of an open element with this name, if in scope
+    fn in_scope(&self, name: &str, extra_boundaries: &[&str]) -> Option<usize> {
+        for (i, &id) in self.stack.iter().enumerate().rev() {
+            let e = self.dom.element(id)?;
+            if e.name == name {
+                return Some(i);
+            }
+            if SCOPE.contains(&e.name.as_str()) || extra_boundaries.contains(&e.name.as_str()) {
+                return None;
+            }
+        }
+        None
+    }
+
+    fn close(&mut self, name: &str, extra_boundaries: &[&str]) {
+        self.close_any(&[name], extra_boundaries);
+    }
+
+    // closes the innermost open element out of names
+    fn close_any(&mut self, names: &[&str], extra_boundaries: &[&str]) -> bool {
+        let open = names
+            .iter()
+            .filter_map(|name| self.in_scope(name, extra_boundaries))
+            .max();
+        match open {
+            Some(i) => {
+                self.stack.truncate(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn insert_text(&mut self, text: String) {
+        let parent = self.current();
+        if let Some(&last) = self.dom.nodes[parent].children.last() {
+            if let NodeData::Text(t) = &mut self.dom.nodes[last].data {
+                t.push_str(&text);
+                return;
+            }
+        }
+        self.dom.append(parent, NodeData::Text(text));
+    }
+
+    fn insert_element(&mut self, tag: Tag) -> usize {
+        let parent = self.current();
+        let element = Element {
+            name: tag.name,
+            attrs: tag.attrs,
+        };
+        self.dom.append(parent, NodeData::Element(element))
+    }
+
+    fn process(&mut self, token: Token) {
+        match token {
+            Token::Text(text) => self.insert_text(text),
+            Token::Comment(_) => {
+                let parent = self.current();
+                self.dom.append(parent, NodeData::Comment);
+            }
+            Token::Doctype(_) => {
+                self.dom.append(Dom::ROOT, NodeData::Doctype);
+            }
+            Token::StartTag(tag) => self.start_tag(tag),
+            Token::EndTag(tag) => self.end_tag(tag),
+        }
+    }
+
+    fn start_tag(&mut self, tag: Tag) {
+        let name = tag.name.as_str();
+        if CLOSES_P.contains(&name) {
+            self.close("p", &["button"]);
+        }
+        match name {
+            "li" => {
+                self.close("li", &["ul", "ol"]);
+            }
+            "dd" | "dt" => {
+                self.close_any(&["dd", "dt"], &["dl"]);
+            }
+            "option" | "optgroup" => {
+                if self.current_name() == Some("option") {
+                    self.stack.pop();
+                }
+                if name == "optgroup" && self.current_name() == Some("optgroup") {
+                    self.stack.pop();
+                }
+            }
+            "tr" => {
+                self.close("tr", &["tbody", "thead", "tfoot"]);
+            }
+            "td" | "th" => {
+                self.close_any(&["td", "th"], &["tr"]);
+            }
+            "a" => {
+                self.close("a", &[]);
+            }
+            _ if HEADINGS.contains(&name)
+                && self.current_name().is_some_and(|n| HEADINGS.contains(&n)) =>
+            {
+                self.stack.pop();
+            }
+            _ => (),
+        }
+
+        let state = match name {
+            "script" => Some(State::ScriptData),
+            "style" | "xmp" | "iframe" | "noembed" | "noframes" => Some(State::RawText),
+            "title" | "textarea" => Some(State::RcData),
+            "plaintext" => Some(State::PlainText),
+            _ => None,
+        };
+        let void = VOID.contains(&name);
+        if !void && self.stack.len() >= MAX_DEPTH {
+            self.stack.pop();
+        }
+        let id = self.insert_element(tag);
+        if !void {
+            self.stack.push(id);
+        }
+        if let Some(state) = state {
+            self.tokenizer.set_state(state);
+        }
+    }
+
+    fn end_tag(&mut self, tag: Tag) {
+        match tag.name.as_str() {
+            "p" => {
+                if !self.close_any(&["p"], &["button"]) {
+                    // a stray </p> still produces an (empty) paragraph
+                    self.insert_element(Tag {
+                        name: "p".to_string(),
+                        ..Tag::new()
+                    });
+                }
+            }
+            "br" => {
+                self.insert_element(Tag {
+                    name: "br".to_string(),
+                    ..Tag::new()
+                });
+            }
+            name if HEADINGS.contains(&name) => {
+                self.close_any(HEADINGS, &[]);
+            }
+            name => {
+                self.close(name, &[]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the tree of html written back out, every element closed
+    fn parse(html: &str) -> String {
+        let mut builder = TreeBuilder::new();
+        builder.feed(html);
+        let dom = builder.finish();
+        let mut out = String::new();
+        dump(&dom, Dom::ROOT, &mut out);
+        out
+    }
+
+    fn dump(dom: &Dom, id: usize, out: &mut String) {
+        let children = |out: &mut String| {
+            for &child in &dom.nodes[id].children {
+                dump(dom, child, out);
+            }
+        };
+        match &dom.nodes[id].data {
+            NodeData::Document => children(out),
+            NodeData::Doctype => out.push_str("<!doctype>"),
+            NodeData::Comment => out.push_str("<!---->"),
+            NodeData::Text(text) => out.push_str(text),
+            NodeData::Element(e) => {
+                out.push('<');
+                out.push_str(&e.name);
+                for (name, value) in &e.attrs {
+                    out.push_str(&format!(" {}=\"{}\"", name, value));
+                }
+                out.push('>');
+                children(out);
+                if !VOID.contains(&e.name.as_str()) {
+                    out.push_str(&format!("</{}>", e.name));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn nesting_is_capped() {
+        let html = "<div>".repeat(10_000) + "deep";
+        let mut builder = TreeBuilder::new();
+        builder.feed(&html);
+        let dom = builder.finish();
+        let mut deepest = 0;
+        let mut walk = vec![(Dom::ROOT, 0)];
+        while let Some((id, depth)) = walk.pop() {
+            deepest = deepest.max(depth);
+            walk.extend(dom.nodes[id].children.iter().map(|&c| (c, depth + 1)));
+        }
+        assert!(deepest <= MAX_DEPTH);
+        assert_eq!(dom.text(Dom::ROOT), "deep");
+        // and it renders, on the small stack of a test thread
+        let url = crate::url::Url::parse("http://example.com/").unwrap();
+        let mut builder = crate::page::PageBuilder::new(url, None, Vec::new());
+        builder.feed(html.as_bytes());
+        assert!(builder.finish().buf.contains("deep"));
+    }
+
+    #[test]
+    fn comments_and_doctype() {
+        assert_eq!(
+            parse("<!DOCTYPE html><p>a<!-- -- -->b<!--x--></p>"),
+            "<!doctype><p>a<!---->b<!----></p>"
+        );
+    }
+
+    #[test]
+    fn cdata() {
+        assert_eq!(parse("<p><![CDATA[a<b>]]></p>"), "<p>a<b></p>");
+    }
+
+    #[test]
+    fn tags_in_attribute_values() {
+        assert_eq!(
+            parse(r#"<a title="<a href=x>" alt='>'>t</a>"#),
+            r#"<a title="<a href=x>" alt=">">t</a>"#
+        );
+    }
+
+    #[test]
+    fn upper_case_and_unclosed_tags() {
+        assert_eq!(
+            parse("<DIV><P>one<P>two<UL><LI>a<LI>b</UL></DIV>after"),
+            "<div><p>one</p><p>two</p><ul><li>a</li><li>b</li></ul></div>after"
+        );
+        assert_eq!(
+            parse("<a href=1>one<a href=2>two"),
+            r#"<a href="1">one</a><a href="2">two</a>"#
+        );
+        assert_eq!(parse("<h1>a<h2>b</h1>c"), "<h1>a</h1><h2>b</h2>c");
+        assert_eq!(parse("a</p>b<br>"), "a<p></p>b<br>");
+        assert_eq!(
+            parse("<select><option>a<option>b</select>"),
+            "<select><option>a</option><option>b</option></select>"
+        );
+    }
+
+    #[test]
+    fn script_and_style_are_raw() {
+        assert_eq!(
+            parse("<script>if (a<b && c>d) x = '</p>'</script><p>"),
+            "<script>if (a<b && c>d) x = '</p>'</script><p></p>"
+        );
+        assert_eq!(parse("<style>a<b { }</STYLE>c"), "<style>a<b { }</style>c");
+        assert_eq!(
+            parse("<title>a &amp; <b></title>"),
+            "<title>a & <b></title>"
+        );
+        assert_eq!(
+            parse("<textarea><p>x</textarea>"),
+            "<textarea><p>x</textarea>"
+        );
+    }
+
+    #[test]
+    fn escaped_script_data() {
+        // a </script> in a script nested in a comment doesn't end the outer one
+        assert_eq!(
+            parse("<script><!--<script>a</script>b--></script>c"),
+            "<script><!--<script>a</script>b--></script>c"
+        );
+        assert_eq!(
+            parse("<script><!-- a </script>b"),
+            "<script><!-- a </script>b"
+        );
+        assert_eq!(
+            parse("<script><!--><script></script>x"),
+            "<script><!--><script></script>x"
+        );
+    }
+
+    #[test]
+    fn entities() {
+        assert_eq!(
+            parse("<p title='&amp=1&amp'>&amp &copy2 &lt;&gt</p>"),
+            "<p title=\"&amp=1&\">& ©2 <></p>"
+        );
+    }
+}
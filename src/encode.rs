@@ -0,0 +1,332 @@
+// request bodies for form submission, picked by the form's enctype
+
+use crate::form::Value;
+use crate::url;
+use encoding_rs::Encoding;
+use std::collections::hash_map::RandomState;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::path::Path;
+
+pub const URLENCODED: &str = "application/x-www-form-urlencoded";
+pub const MULTIPART: &str = "multipart/form-data";
+pub const TEXT_PLAIN: &str = "text/plain";
+
+// returns the content type and the body. names and values go in charset,
+// the page's, with what it can't hold as &#...; references.
+pub fn encode(
+    enctype: &str,
+    entries: &[(String, Value)],
+    charset: &'static Encoding,
+) -> io::Result<(String, Vec<u8>)> {
+    match enctype {
+        MULTIPART => multipart(entries, charset),
+        TEXT_PLAIN => Ok((TEXT_PLAIN.to_string(), text_plain(entries, charset))),
+        _ => Ok((
+            URLENCODED.to_string(),
+            urlencoded(entries, charset).into_bytes(),
+        )),
+    }
+}
+
+// utf-16 pages send utf-8, encoding_rs sees to that
+fn in_charset(s: &str, charset: &'static Encoding) -> Vec<u8> {
+    charset.encode(s).0.into_owned()
+}
+
+// files can't go in a query, only their names do
+fn as_text(entries: &[(String, Value)]) -> Vec<(String, String)> {
+    entries
+        .iter()
+        .map(|(name, value)| match value {
+            Value::Text(text) => (name.clone(), text.clone()),
+            Value::File(path) => (name.clone(), file_name(path)),
+        })
+        .collect()
+}
+
+pub fn urlencoded(entries: &[(String, Value)], charset: &'static Encoding) -> String {
+    let pairs: Vec<(Vec<u8>, Vec<u8>)> = as_text(entries)
+        .iter()
+        .map(|(name, value)| (in_charset(name, charset), in_charset(value, charset)))
+        .collect();
+    url::form_urlencode(&pairs)
+}
+
+fn text_plain(entries: &[(String, Value)], charset: &'static Encoding) -> Vec<u8> {
+    let text: String = as_text(entries)
+        .iter()
+        .map(|(name, value)| format!("{}={}\r\n", name, value))
+        .collect();
+    in_charset(&text, charset)
+}
+
+// reads the files of file fields into the body. a file field left empty
+// is sent as an empty part with no file name, like browsers do.
+pub fn multipart(
+    entries: &[(String, Value)],
+    charset: &'static Encoding,
+) -> io::Result<(String, Vec<u8>)> {
+    let mut parts = Vec::new();
+    for (name, value) in entries {
+        let mut head = format!("Content-Disposition: form-data; name=\"{}\"", escape(name));
+        let data = match value {
+            Value::Text(text) => in_charset(text, charset),
+            Value::File(path) => {
+                let data = match path.is_empty() {
+                    true => Vec::new(),
+                    false => fs::read(path)
+                        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))?,
+                };
+                head.push_str(&format!(
+                    "; filename=\"{}\"\r\nContent-Type: {}",
+                    escape(&file_name(path)),
+                    mime_type(path)
+                ));
+                data
+            }
+        };
+        parts.push((in_charset(&head, charset), data));
+    }
+
+    let boundary = pick_boundary(&parts, boundary);
+    let mut body = Vec::new();
+    for (head, data) in parts {
+        body.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+        body.extend_from_slice(&head);
+        body.extend_from_slice(b"\r\n\r\n");
+        body.extend_from_slice(&data);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+    let content_type = format!("{}; boundary={}", MULTIPART, boundary);
+    Ok((content_type, body))
+}
+
+// the boundary must not turn up inside any of the parts
+fn pick_boundary(parts: &[(Vec<u8>, Vec<u8>)], mut next: impl FnMut() -> String) -> String {
+    loop {
+        let boundary = next();
+        let found = parts.iter().any(|(head, data)| {
+            [head, data].iter().any(|bytes| {
+                bytes
+                    .windows(boundary.len())
+                    .any(|w| w == boundary.as_bytes())
+            })
+        });
+        if !found {
+            return boundary;
+        }
+    }
+}
+
+fn boundary() -> String {
+    // every RandomState is seeded differently, which is random enough here
+    let random = || RandomState::new().build_hasher().finish();
+    format!("----ibrowFormBoundary{:016x}{:016x}", random(), random())
+}
+
+// quotes and line breaks can't appear in a header parameter
+fn escape(s: &str) -> String {
+    s.replace('\r', "%0D")
+        .replace('\n', "%0A")
+        .replace('"', "%22")
+}
+
+fn file_name(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn mime_type(path: &str) -> &'static str {
+    let ext = Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase);
+    match ext.as_deref() {
+        Some("txt") => "text/plain",
+        Some("html" | "htm") => "text/html",
+        Some("css") => "text/css",
+        Some("js") => "text/javascript",
+        Some("json") => "application/json",
+        Some("pdf") => "application/pdf",
+        Some("zip") => "application/zip",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("svg") => "image/svg+xml",
+        Some("webp") => "image/webp",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::{Fetcher, Request};
+    use crate::http::tests::{client, response, serve_received, Collect, Received};
+    use crate::url::Url;
+    use encoding_rs::{SHIFT_JIS, UTF_8, WINDOWS_1252};
+
+    fn text(entries: &[(&str, &str)]) -> Vec<(String, Value)> {
+        entries
+            .iter()
+            .map(|(name, value)| (name.to_string(), Value::Text(value.to_string())))
+            .collect()
+    }
+
+    // posts the entries through the client to a loopback server, returns
+    // the content type and what the server got
+    fn send(
+        enctype: &str,
+        entries: &[(&str, &str)],
+        charset: &'static Encoding,
+    ) -> (String, Received) {
+        let (content_type, body) = encode(enctype, &text(entries), charset).unwrap();
+        let (port, received) = serve_received(|_| response("", b"ok"));
+        let url = Url::parse(&format!("http://127.0.0.1:{}/form", port)).unwrap();
+        let req = Request::post(url, &content_type, body.clone());
+        client(None).fetch(&req, &mut Collect::default()).unwrap();
+        let got = received.lock().unwrap().remove(0);
+        assert_eq!(got.header("Content-Type"), Some(content_type.as_str()));
+        let len = body.len().to_string();
+        assert_eq!(got.header("Content-Length"), Some(len.as_str()));
+        assert_eq!(got.body, body);
+        (content_type, got)
+    }
+
+    fn decode(bytes: &[u8], charset: &'static Encoding) -> String {
+        charset.decode_without_bom_handling(bytes).0.into_owned()
+    }
+
+    // what a server makes of the bodies
+    fn parse_urlencoded(body: &[u8], charset: &'static Encoding) -> Vec<(String, String)> {
+        let s = std::str::from_utf8(body).unwrap();
+        let field = |s: &str| decode(&url::percent_decode(&s.replace('+', " ")), charset);
+        s.split('&')
+            .map(|pair| {
+                let (name, value) = pair.split_once('=').unwrap();
+                (field(name), field(value))
+            })
+            .collect()
+    }
+
+    fn parse_multipart(
+        content_type: &str,
+        body: &[u8],
+        charset: &'static Encoding,
+    ) -> Vec<(String, String)> {
+        let boundary = content_type.split_once("boundary=").unwrap().1;
+        let delimiter = format!("\r\n--{}", boundary);
+        // the first delimiter has no line break before it
+        let mut body = [b"\r\n", body].concat();
+        let mut parts = Vec::new();
+        loop {
+            let at = find(&body, delimiter.as_bytes()).unwrap();
+            if !parts.is_empty() || at > 0 {
+                parts.push(body[..at].to_vec());
+            }
+            body.drain(..at + delimiter.len());
+            if body.starts_with(b"--") {
+                assert_eq!(body, b"--\r\n");
+                break;
+            }
+            assert!(body.starts_with(b"\r\n"));
+            body.drain(..2);
+        }
+        parts
+            .iter()
+            .map(|part| {
+                let split = find(part, b"\r\n\r\n").unwrap();
+                let head = decode(&part[..split], charset);
+                let name = head.split_once("name=\"").unwrap().1;
+                let name = &name[..name.find('"').unwrap()];
+                (name.to_string(), decode(&part[split + 4..], charset))
+            })
+            .collect()
+    }
+
+    fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+        haystack.windows(needle.len()).position(|w| w == needle)
+    }
+
+    fn owned(entries: &[(&str, &str)]) -> Vec<(String, String)> {
+        entries
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn urlencoded_keeps_plus_and_percent() {
+        let entries = [("q", "a+b%20c d"), ("x y", "&="), ("", "")];
+        let (content_type, got) = send(URLENCODED, &entries, UTF_8);
+        assert_eq!(content_type, URLENCODED);
+        assert_eq!(got.body, b"q=a%2Bb%2520c+d&x+y=%26%3D&=");
+        assert_eq!(parse_urlencoded(&got.body, UTF_8), owned(&entries));
+    }
+
+    #[test]
+    fn urlencoded_in_the_page_charset() {
+        let entries = [("café", "naïve €")];
+        let (_, got) = send(URLENCODED, &entries, WINDOWS_1252);
+        assert_eq!(got.body, b"caf%E9=na%EFve+%80");
+        assert_eq!(parse_urlencoded(&got.body, WINDOWS_1252), owned(&entries));
+
+        let entries = [("名前", "日本 語")];
+        let (_, got) = send(URLENCODED, &entries, SHIFT_JIS);
+        assert_eq!(got.body, b"%96%BC%91O=%93%FA%96%7B+%8C%EA");
+        assert_eq!(parse_urlencoded(&got.body, SHIFT_JIS), owned(&entries));
+
+        // what the charset has no room for goes as a character reference
+        let (_, got) = send(URLENCODED, &[("日", "x")], WINDOWS_1252);
+        assert_eq!(got.body, b"%26%2326085%3B=x");
+    }
+
+    #[test]
+    fn multipart_round_trip() {
+        let entries = [
+            ("plain", "a+b%20c"),
+            ("lines", "one\r\ntwo\r\n\r\n"),
+            ("fake", "--\r\n------ibrowFormBoundary0000\r\n--"),
+            ("quote\"d", ""),
+        ];
+        let (content_type, got) = send(MULTIPART, &entries, UTF_8);
+        assert!(content_type.starts_with("multipart/form-data; boundary="));
+        let mut expected = owned(&entries);
+        expected[3].0 = "quote%22d".to_string();
+        assert_eq!(parse_multipart(&content_type, &got.body, UTF_8), expected);
+    }
+
+    #[test]
+    fn multipart_in_the_page_charset() {
+        let entries = [("名前", "日本語"), ("ü", "ß")];
+        let (content_type, got) = send(MULTIPART, &entries, SHIFT_JIS);
+        assert!(find(&got.body, b"name=\"\x96\xbc\x91\x4f\"").is_some());
+        let parsed = parse_multipart(&content_type, &got.body, SHIFT_JIS);
+        assert_eq!(parsed[0], owned(&entries)[0]);
+        // shift_jis has neither
+        assert_eq!(parsed[1], ("&#252;".to_string(), "&#223;".to_string()));
+    }
+
+    #[test]
+    fn text_plain_in_the_page_charset() {
+        let (content_type, got) = send(TEXT_PLAIN, &[("a", "b c"), ("é", "€")], WINDOWS_1252);
+        assert_eq!(content_type, TEXT_PLAIN);
+        assert_eq!(got.body, b"a=b c\r\n\xe9=\x80\r\n");
+    }
+
+    #[test]
+    fn boundary_is_not_in_the_data() {
+        let parts = [
+            (b"name=\"a\"".to_vec(), b"xx--one--xx".to_vec()),
+            (b"name=\"two\"".to_vec(), Vec::new()),
+        ];
+        let mut candidates = ["--one--", "two", "three"].into_iter();
+        let picked = pick_boundary(&parts, || candidates.next().unwrap().to_string());
+        assert_eq!(picked, "three");
+    }
+}
@@ -0,0 +1,459 @@
+// fetch backends. pages can come from the in-process client, from a curl
+// subprocess, or from a directory of canned pages for testing.
+
+use crate::cookie::SharedJar;
+use crate::http::{self, Sink};
+use crate::url::Url;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread;
+
+// what every request goes out with, whichever backend sends it
+#[derive(Clone)]
+pub struct Settings {
+    pub user_agent: String,
+    pub proxy: Option<Url>,
+    pub headers: Vec<(String, String)>,
+}
+
+pub struct Request {
+    pub method: String,
+    pub url: Url,
+    pub content_type: Option<String>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn get(url: Url) -> Request {
+        Request {
+            method: "GET".to_string(),
+            url,
+            content_type: None,
+            body: Vec::new(),
+        }
+    }
+
+    pub fn post(url: Url, content_type: &str, body: Vec<u8>) -> Request {
+        Request {
+            method: "POST".to_string(),
+            url,
+            content_type: Some(content_type.to_string()),
+            body,
+        }
+    }
+}
+
+// the response is handed to the sink as it arrives rather than returned,
+// so pages can be drawn while they load
+pub trait Fetcher: Send {
+    fn fetch(&mut self, req: &Request, sink: &mut dyn Sink) -> io::Result<()>;
+
+    // one going the same way with nothing open, for a new worker to use
+    // while this one is stuck on a canceled fetch
+    fn fresh(&self) -> Box<dyn Fetcher>;
+}
+
+// "native", "curl" or "mock:<dir>"
+pub fn from_name(
+    name: &str,
+    settings: &Settings,
+    cookies: SharedJar,
+) -> io::Result<Box<dyn Fetcher>> {
+    match name {
+        "" | "native" => Ok(Box::new(http::Client::new(settings, cookies))),
+        "curl" => Ok(Box::new(Curl::new(settings, cookies))),
+        _ => match name.strip_prefix("mock:") {
+            Some(dir) => Ok(Box::new(Mock::new(dir))),
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown fetch backend: {}", name),
+            )),
+        },
+    }
+}
+
+impl Fetcher for http::Client {
+    fn fetch(&mut self, req: &Request, sink: &mut dyn Sink) -> io::Result<()> {
+        let mut headers = Vec::new();
+        if let Some(content_type) = &req.content_type {
+            headers.push(("Content-Type", content_type.as_str()));
+        }
+        self.request(&req.method, &req.url, &headers, &req.body, sink)
+    }
+
+    fn fresh(&self) -> Box<dyn Fetcher> {
+        Box::new(self.without_connections())
+    }
+}
+
+#[derive(Clone)]
+pub struct Curl {
+    settings: Settings,
+    cookies: SharedJar,
+}
+
+impl Curl {
+    pub fn new(settings: &Settings, cookies: SharedJar) -> Curl {
+        Curl {
+            settings: settings.clone(),
+            cookies,
+        }
+    }
+
+    fn run(&self, req: &Request, sink: &mut dyn Sink) -> io::Result<()> {
+        let mut curl = Command::new("curl");
+        // headers of every hop are dumped before the body
+        curl.args(["-sS", "-L", "--compressed", "-N", "-D", "-"]);
+        curl.args(["-A", &self.settings.user_agent]);
+        if let Some(proxy) = &self.settings.proxy {
+            curl.args(["-x", &proxy.to_string()]);
+        }
+        for (name, value) in &self.settings.headers {
+            curl.args(["-H", &format!("{}: {}", name, value)]);
+        }
+        // curl keeps no cookies of its own, these only go with the first
+        // request. set-cookie headers of every hop come back into the jar.
+        if let Some(cookie) = self.cookies.lock().unwrap().header(&req.url) {
+            curl.args(["-H", &format!("Cookie: {}", cookie)]);
+        }
+        if let Some(content_type) = &req.content_type {
+            let header = format!("Content-Type: {}", content_type);
+            curl.args(["-H", &header, "--data-binary", "@-"]);
+        } else if req.method != "GET" {
+            curl.args(["-X", &req.method]);
+        }
+        curl.arg(req.url.to_string());
+        curl.stdin(Stdio::piped());
+        curl.stdout(Stdio::piped());
+        curl.stderr(Stdio::piped());
+
+        let mut child = curl.spawn()?;
+        let mut stdin = child.stdin.take().unwrap();
+        let body = req.body.clone();
+        let writer = thread::spawn(move || stdin.write_all(&body));
+        let mut out = BufReader::new(child.stdout.take().unwrap());
+        let res = stream_curl_output(&mut out, &req.url, &self.cookies, sink);
+        if res.is_err() {
+            let _ = child.kill();
+        }
+        let _ = writer.join();
+        let mut err = String::new();
+        if let Some(mut stderr) = child.stderr.take() {
+            let _ = stderr.read_to_string(&mut err);
+        }
+        let status = child.wait()?;
+        res?;
+        if !status.success() {
+            return Err(io::Error::other(err.trim().to_string()));
+        }
+        Ok(())
+    }
+}
+
+impl Fetcher for Curl {
+    fn fetch(&mut self, req: &Request, sink: &mut dyn Sink) -> io::Result<()> {
+        self.run(req, sink)
+    }
+
+    fn fresh(&self) -> Box<dyn Fetcher> {
+        Box::new(self.clone())
+    }
+}
+
+fn stream_curl_output(
+    out: &mut impl BufRead,
+    url: &Url,
+    cookies: &SharedJar,
+    sink: &mut dyn Sink,
+) -> io::Result<()> {
+    let mut url = url.clone();
+    let mut status = 0;
+    let mut headers = Vec::new();
+    // the proxy's answer to connect, interim responses and redirects are
+    // followed by another header block. the final url is worked out from
+    // the location headers along the way.
+    loop {
+        let mut line = Vec::new();
+        if out.read_until(b'\n', &mut line)? == 0 {
+            // curl failed before any response, its exit status tells why
+            return Ok(());
+        }
+        let line: String = line.iter().map(|&b| b as char).collect();
+        let line = line.trim_end();
+        if line.starts_with("HTTP/") {
+            status = line
+                .split(' ')
+                .nth(1)
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(0);
+            headers.clear();
+            continue;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+            continue;
+        }
+        if !line.is_empty() {
+            continue;
+        }
+        http::store_cookies(cookies, &url, &headers);
+        let location = headers
+            .iter()
+            .find(|(n, _): &&(String, String)| n.eq_ignore_ascii_case("location"))
+            .map(|(_, v)| v.as_str());
+        if let Some(location) = location.filter(|_| (300..400).contains(&status)) {
+            url = url.join(location)?;
+        }
+        if !is_status_line(out.fill_buf()?) {
+            break;
+        }
+    }
+    sink.head(status, &headers, &url);
+
+    loop {
+        let buf = out.fill_buf()?;
+        if buf.is_empty() {
+            return Ok(());
+        }
+        if !sink.data(buf) {
+            return Err(http::aborted());
+        }
+        let n = buf.len();
+        out.consume(n);
+    }
+}
+
+// whether buf starts with a status line, as "HTTP/1.1 200", rather than the
+// body
+fn is_status_line(buf: &[u8]) -> bool {
+    let Some(rest) = buf.strip_prefix(b"HTTP/") else {
+        return false;
+    };
+    let Some(space) = rest.iter().position(|&b| b == b' ') else {
+        return false;
+    };
+    let (version, status) = (&rest[..space], &rest[space + 1..]);
+    version.iter().all(|b| b.is_ascii_digit() || *b == b'.')
+        && status.len() >= 3
+        && status[..3].iter().all(u8::is_ascii_digit)
+}
+
+// serves files under a directory, http://host/a/b is read from <dir>/host/a/b
+#[derive(Clone)]
+pub struct Mock {
+    root: PathBuf,
+}
+
+impl Mock {
+    pub fn new(root: &str) -> Mock {
+        Mock { root: root.into() }
+    }
+}
+
+impl Fetcher for Mock {
+    fn fetch(&mut self, req: &Request, sink: &mut dyn Sink) -> io::Result<()> {
+        let url = &req.url;
+        let mut file = self.root.join(url.host().unwrap_or_default());
+        file.push(url.path().trim_start_matches('/'));
+        if url.path().ends_with('/') {
+            file.push("index.html");
+        }
+        let (status, body) = match fs::read(&file) {
+            Ok(body) => (200, body),
+            Err(_) => (404, b"<p>not found</p>".to_vec()),
+        };
+        let content_type = match file.extension().and_then(|e| e.to_str()) {
+            Some("html" | "htm") | None => "text/html",
+            Some(_) => "application/octet-stream",
+        };
+        let headers = vec![("Content-Type".to_string(), content_type.to_string())];
+        sink.head(status, &headers, url);
+        match sink.data(&body) {
+            true => Ok(()),
+            false => Err(http::aborted()),
+        }
+    }
+
+    fn fresh(&self) -> Box<dyn Fetcher> {
+        Box::new(self.clone())
+    }
+}
+
+// the mock pages are used by the tests of tabs too
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::cookie::Jar;
+    use crate::loader::{Event, Loader};
+    use crate::page::{Page, PageBuilder};
+    use std::io::Cursor;
+    use std::time::{Duration, Instant};
+
+    #[derive(Default)]
+    struct Collect {
+        status: u16,
+        headers: Vec<(String, String)>,
+        url: String,
+        body: Vec<u8>,
+    }
+
+    impl Sink for Collect {
+        fn head(&mut self, status: u16, headers: &[(String, String)], url: &Url) {
+            self.status = status;
+            self.headers = headers.to_vec();
+            self.url = url.to_string();
+        }
+
+        fn data(&mut self, data: &[u8]) -> bool {
+            self.body.extend_from_slice(data);
+            true
+        }
+    }
+
+    fn curl_output(output: &str) -> Collect {
+        let url = Url::parse("https://example.com/a").unwrap();
+        let cookies = Jar::load(None).shared();
+        let mut sink = Collect::default();
+        stream_curl_output(&mut Cursor::new(output), &url, &cookies, &mut sink).unwrap();
+        sink
+    }
+
+    #[test]
+    fn curl_output_skips_the_proxy_connect() {
+        let sink = curl_output(
+            "HTTP/1.1 200 Connection established\r\n\r\n\
+             HTTP/2 404 \r\ncontent-type: text/html\r\n\r\n<p>gone",
+        );
+        assert_eq!(sink.status, 404);
+        assert_eq!(
+            sink.headers,
+            [("content-type".to_string(), "text/html".to_string())]
+        );
+        assert_eq!(sink.body, b"<p>gone");
+    }
+
+    #[test]
+    fn curl_output_follows_interim_and_redirect_heads() {
+        let sink = curl_output(
+            "HTTP/1.1 200 Connection established\r\n\r\n\
+             HTTP/1.1 100 Continue\r\n\r\n\
+             HTTP/1.1 301 Moved\r\nLocation: /b\r\n\r\n\
+             HTTP/1.1 302 Found\r\nLocation: c?d\r\nSet-Cookie: a=1\r\n\r\n\
+             HTTP/1.1 200 OK\r\nX: y\r\n\r\nHTTP/1.1 in the body",
+        );
+        assert_eq!(sink.status, 200);
+        assert_eq!(sink.url, "https://example.com/c?d");
+        assert_eq!(sink.headers, [("X".to_string(), "y".to_string())]);
+        assert_eq!(sink.body, b"HTTP/1.1 in the body");
+    }
+
+    #[test]
+    fn curl_output_keeps_a_redirect_curl_did_not_follow() {
+        let sink = curl_output("HTTP/1.1 302 Found\r\nLocation: /b\r\n\r\n");
+        assert_eq!(sink.status, 302);
+        assert!(sink.body.is_empty());
+    }
+
+    // a directory of canned pages, removed when dropped
+    pub(crate) struct Pages(pub PathBuf);
+
+    impl Pages {
+        pub fn new(name: &str, files: &[(&str, &str)]) -> Pages {
+            let dir = std::env::temp_dir().join(format!("ibrow-{}-{}", name, std::process::id()));
+            for (path, text) in files {
+                let file = dir.join(path);
+                fs::create_dir_all(file.parent().unwrap()).unwrap();
+                fs::write(file, text).unwrap();
+            }
+            Pages(dir)
+        }
+
+        pub fn fetcher(&self) -> Box<dyn Fetcher> {
+            let settings = Settings {
+                user_agent: String::new(),
+                proxy: None,
+                headers: Vec::new(),
+            };
+            let name = format!("mock:{}", self.0.display());
+            from_name(&name, &settings, Jar::load(None).shared()).unwrap()
+        }
+    }
+
+    impl Drop for Pages {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    // loads url on the loader's worker, as a tab does, into a page
+    fn load(loader: &mut Loader, url: &str) -> io::Result<Page> {
+        loader.start(Request::get(Url::parse(url).unwrap()));
+        let mut builder = None;
+        let started = Instant::now();
+        while started.elapsed() < Duration::from_secs(5) {
+            match loader.poll() {
+                Some(Event::Head {
+                    status,
+                    headers,
+                    url,
+                }) => builder = Some(PageBuilder::new(url, Some(status), headers)),
+                Some(Event::Data(data)) => builder.as_mut().unwrap().feed(&data),
+                Some(Event::Done(res)) => return res.map(|_| builder.unwrap().finish()),
+                None => thread::sleep(Duration::from_millis(1)),
+            }
+        }
+        panic!("{} did not load", url);
+    }
+
+    #[test]
+    fn mock_pages_load_through_the_loader() {
+        let pages = Pages::new(
+            "mock",
+            &[
+                (
+                    "example.com/index.html",
+                    "<title>Home</title><p>hello <a href=docs/a.html>docs</a>",
+                ),
+                ("example.com/docs/a.html", "<h1>A</h1><a href=../>up</a>"),
+                ("example.com/file.bin", "data"),
+            ],
+        );
+        let mut loader = Loader::new(pages.fetcher());
+
+        let home = load(&mut loader, "http://example.com/").unwrap();
+        assert_eq!(home.info.status, Some(200));
+        assert_eq!(home.title(), "Home");
+        assert!(home.buf.contains("hello"));
+        let docs = home.resolve(&home.anchors[0]).unwrap();
+        assert_eq!(docs.to_string(), "http://example.com/docs/a.html");
+
+        let a = load(&mut loader, &docs.to_string()).unwrap();
+        assert_eq!(
+            a.resolve(&a.anchors[0]).unwrap().to_string(),
+            "http://example.com/"
+        );
+
+        let file = load(&mut loader, "http://example.com/file.bin").unwrap();
+        assert_eq!(file.info.content_type(), Some("application/octet-stream"));
+
+        let missing = load(&mut loader, "http://example.com/nothing").unwrap();
+        assert_eq!(missing.info.status, Some(404));
+        assert!(missing.buf.contains("not found"));
+    }
+
+    #[test]
+    fn unknown_backends_are_refused() {
+        let settings = Settings {
+            user_agent: String::new(),
+            proxy: None,
+            headers: Vec::new(),
+        };
+        let err = from_name("carrier-pigeon", &settings, Jar::load(None).shared()).err();
+        assert_eq!(
+            err.unwrap().to_string(),
+            "unknown fetch backend: carrier-pigeon"
+        );
+    }
+}
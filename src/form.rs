@@ -0,0 +1,351 @@
+// forms and their fields. the renderer collects them as it walks the dom,
+// the user fills them in by index, and submitting turns them into the
+// entry list the spec sends to the server.
+
+use crate::dom::Element;
+
+#[derive(Clone)]
+pub struct Form {
+    pub action: Option<String>,
+    pub method: String,
+    pub enctype: String,
+}
+
+impl Form {
+    pub fn new(e: &Element) -> Form {
+        let method = match e.attr("method").map(str::to_ascii_lowercase) {
+            Some(m) if m == "post" || m == "dialog" => m,
+            _ => "get".to_string(),
+        };
+        let enctype = match e.attr("enctype").map(str::to_ascii_lowercase) {
+            Some(t) if t == "multipart/form-data" || t == "text/plain" => t,
+            _ => "application/x-www-form-urlencoded".to_string(),
+        };
+        Form {
+            action: e.attr("action").map(str::to_string),
+            method,
+            enctype,
+        }
+    }
+}
+
+// what a field sends, a file field sends the file at its path
+pub enum Value {
+    Text(String),
+    File(String),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Kind {
+    Text,
+    Password,
+    Hidden,
+    Checkbox,
+    Radio,
+    File,
+    Submit,
+    Image,
+    Reset,
+    Button,
+    Select,
+    Textarea,
+}
+
+#[derive(Clone)]
+pub struct SelectOption {
+    pub value: String,
+    pub label: String,
+    pub selected: bool,
+}
+
+#[derive(Clone)]
+pub struct Field {
+    // index into the page's forms, None for a field outside of any form
+    pub form: Option<usize>,
+    pub kind: Kind,
+    pub name: String,
+    pub value: String,
+    pub checked: bool,
+    pub disabled: bool,
+    pub options: Vec<SelectOption>,
+    pub multiple: bool,
+    // the text inside a <button>
+    pub label: String,
+}
+
+impl Field {
+    fn new(e: &Element, form: Option<usize>, kind: Kind) -> Field {
+        Field {
+            form,
+            kind,
+            name: e.attr("name").unwrap_or_default().to_string(),
+            value: e.attr("value").unwrap_or_default().to_string(),
+            checked: e.attr("checked").is_some(),
+            disabled: e.attr("disabled").is_some(),
+            options: Vec::new(),
+            multiple: e.attr("multiple").is_some(),
+            label: String::new(),
+        }
+    }
+
+    pub fn input(e: &Element, form: Option<usize>) -> Field {
+        let kind = match e.attr("type").map(str::to_ascii_lowercase).as_deref() {
+            Some("password") => Kind::Password,
+            Some("hidden") => Kind::Hidden,
+            Some("checkbox") => Kind::Checkbox,
+            Some("radio") => Kind::Radio,
+            Some("file") => Kind::File,
+            Some("submit") => Kind::Submit,
+            Some("image") => Kind::Image,
+            Some("reset") => Kind::Reset,
+            Some("button") => Kind::Button,
+            _ => Kind::Text,
+        };
+        let mut field = Field::new(e, form, kind);
+        match kind {
+            Kind::Checkbox | Kind::Radio if e.attr("value").is_none() => {
+                field.value = "on".to_string();
+            }
+            // the file sent is only ever one the user names, never the page
+            Kind::File => field.value.clear(),
+            _ => (),
+        }
+        field
+    }
+
+    pub fn button(e: &Element, form: Option<usize>, label: &str) -> Field {
+        let kind = match e.attr("type").map(str::to_ascii_lowercase).as_deref() {
+            Some("reset") => Kind::Reset,
+            Some("button") => Kind::Button,
+            _ => Kind::Submit,
+        };
+        let mut field = Field::new(e, form, kind);
+        field.label = collapse(label);
+        field
+    }
+
+    pub fn textarea(e: &Element, form: Option<usize>, text: &str) -> Field {
+        let mut field = Field::new(e, form, Kind::Textarea);
+        // a newline right after <textarea> is not part of the value
+        field.value = text.strip_prefix('\n').unwrap_or(text).to_string();
+        field
+    }
+
+    pub fn select(e: &Element, form: Option<usize>, mut options: Vec<SelectOption>) -> Field {
+        let mut field = Field::new(e, form, Kind::Select);
+        if !field.multiple && !options.iter().any(|o| o.selected) {
+            if let Some(first) = options.first_mut() {
+                first.selected = true;
+            }
+        }
+        // only the last selected option counts in a single select
+        if !field.multiple {
+            if let Some(last) = options.iter().rposition(|o| o.selected) {
+                for (i, o) in options.iter_mut().enumerate() {
+                    o.selected = i == last;
+                }
+            }
+        }
+        field.options = options;
+        field
+    }
+
+    pub fn option(e: &Element, text: &str) -> SelectOption {
+        let label = collapse(text);
+        SelectOption {
+            value: e
+                .attr("value")
+                .map_or_else(|| label.clone(), str::to_string),
+            label,
+            selected: e.attr("selected").is_some(),
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.kind != Kind::Hidden
+    }
+
+    pub fn is_submit(&self) -> bool {
+        matches!(self.kind, Kind::Submit | Kind::Image)
+    }
+
+    // what goes between the braces on the page
+    pub fn display(&self) -> String {
+        match self.kind {
+            Kind::Text | Kind::Hidden | Kind::File => format!("{}={}", self.name, self.value),
+            Kind::Textarea => format!("{}={}", self.name, self.value.replace('\n', "\\n")),
+            Kind::Password => format!("{}={}", self.name, "*".repeat(self.value.chars().count())),
+            Kind::Checkbox => format!("[{}] {}", mark(self.checked, 'x'), self.name),
+            Kind::Radio => format!("({}) {}={}", mark(self.checked, '*'), self.name, self.value),
+            Kind::Select => format!("{}=<{}>", self.name, self.selected_labels()),
+            Kind::Submit | Kind::Image | Kind::Reset | Kind::Button => {
+                format!("[{}]", self.button_label())
+            }
+        }
+    }
+
+    fn button_label(&self) -> &str {
+        match (self.label.as_str(), self.value.as_str(), self.kind) {
+            ("", "", Kind::Reset) => "Reset",
+            ("", "", _) => "Submit",
+            ("", value, _) => value,
+            (label, _, _) => label,
+        }
+    }
+
+    pub fn selected_labels(&self) -> String {
+        let labels: Vec<&str> = self
+            .options
+            .iter()
+            .filter(|o| o.selected)
+            .map(|o| o.label.as_str())
+            .collect();
+        labels.join(", ")
+    }
+
+    pub fn option_list(&self) -> String {
+        let labels: Vec<&str> = self.options.iter().map(|o| o.label.as_str()).collect();
+        labels.join(" | ")
+    }
+
+    // sets a text value, or picks options by value or label. a multiple
+    // select takes a comma separated list.
+    pub fn fill(&mut self, input: &str) -> Result<(), String> {
+        if self.kind != Kind::Select {
+            self.value = input.to_string();
+            return Ok(());
+        }
+        let wanted: Vec<&str> = match self.multiple {
+            true => input
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .collect(),
+            false => vec![input.trim()],
+        };
+        let mut picked = vec![false; self.options.len()];
+        for w in wanted {
+            let found = self
+                .options
+                .iter()
+                .position(|o| o.value == w || o.label.eq_ignore_ascii_case(w));
+            match found {
+                Some(i) => picked[i] = true,
+                None => return Err(format!("{} has no option {}", self.name, w)),
+            }
+        }
+        for (o, picked) in self.options.iter_mut().zip(picked) {
+            o.selected = picked;
+        }
+        Ok(())
+    }
+}
+
+fn mark(on: bool, c: char) -> char {
+    match on {
+        true => c,
+        false => ' ',
+    }
+}
+
+fn collapse(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// checks a checkbox on and off, a radio button on and the rest of its group off
+pub fn toggle(fields: &mut [Field], id: usize) {
+    let (form, name) = (fields[id].form, fields[id].name.clone());
+    match fields[id].kind {
+        Kind::Checkbox => fields[id].checked = !fields[id].checked,
+        Kind::Radio => {
+            for (i, f) in fields.iter_mut().enumerate() {
+                if f.kind == Kind::Radio && f.form == form && f.name == name {
+                    f.checked = i == id;
+                }
+            }
+        }
+        _ => (),
+    }
+}
+
+// the name/value pairs a form sends, in tree order. submitter is the
+// button it was submitted with, if any.
+pub fn entries(fields: &[Field], form: usize, submitter: Option<usize>) -> Vec<(String, Value)> {
+    let mut entries = Vec::new();
+    for (i, f) in fields.iter().enumerate() {
+        if f.form != Some(form) || f.disabled {
+            continue;
+        }
+        match f.kind {
+            Kind::Image if submitter == Some(i) => {
+                // we have no click position, so report the top left corner
+                let prefix = match f.name.is_empty() {
+                    true => String::new(),
+                    false => format!("{}.", f.name),
+                };
+                entries.push((format!("{}x", prefix), Value::Text("0".to_string())));
+                entries.push((format!("{}y", prefix), Value::Text("0".to_string())));
+                continue;
+            }
+            _ if f.name.is_empty() => continue,
+            Kind::Submit if submitter == Some(i) => (),
+            Kind::Submit | Kind::Image | Kind::Reset | Kind::Button => continue,
+            Kind::Checkbox | Kind::Radio if !f.checked => continue,
+            Kind::Select => {
+                for o in f.options.iter().filter(|o| o.selected) {
+                    entries.push((f.name.clone(), Value::Text(o.value.clone())));
+                }
+                continue;
+            }
+            // line breaks are sent as crlf
+            Kind::Textarea => {
+                let value = f.value.replace("\r\n", "\n").replace('\n', "\r\n");
+                entries.push((f.name.clone(), Value::Text(value)));
+                continue;
+            }
+            Kind::File => {
+                entries.push((f.name.clone(), Value::File(f.value.clone())));
+                continue;
+            }
+            _ => (),
+        }
+        entries.push((f.name.clone(), Value::Text(f.value.clone())));
+    }
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::page::{Page, PageBuilder};
+    use crate::url::Url;
+    use std::fs;
+
+    fn page(html: &str) -> Page {
+        let url = Url::parse("http://example.com/form").unwrap();
+        let mut builder = PageBuilder::new(url, Some(200), Vec::new());
+        builder.feed(html.as_bytes());
+        builder.finish()
+    }
+
+    #[test]
+    fn a_page_cant_pick_the_file_to_send() {
+        let secret = std::env::temp_dir().join(format!("ibrow-secret-{}", std::process::id()));
+        fs::write(&secret, "the secret").unwrap();
+        let html = format!(
+            "<form method=post enctype=multipart/form-data>\
+             <input type=file name=f value=\"{}\"><input type=submit></form>",
+            secret.display()
+        );
+        let mut page = page(&html);
+        let req = page.submit(0, None).unwrap();
+        let body = String::from_utf8_lossy(&req.body).into_owned();
+        assert!(body.contains("name=\"f\"; filename=\"\"\r\n"), "{}", body);
+        assert!(!body.contains("the secret"));
+
+        // what the user names does go
+        page.fill(0, &secret.display().to_string()).unwrap();
+        let req = page.submit(0, None).unwrap();
+        assert!(String::from_utf8_lossy(&req.body).contains("the secret"));
+        fs::remove_file(secret).unwrap();
+    }
+}
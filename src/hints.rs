@@ -0,0 +1,179 @@
+// hint mode: the links on screen get labels of home row letters, typing
+// one picks its link. what is done with it can be changed while typing.
+
+use crate::config;
+use crate::keys;
+use crate::page::Page;
+use crate::pager;
+use crossterm::cursor;
+use crossterm::event::{read, Event, KeyCode, KeyModifiers};
+use crossterm::style::Stylize;
+use crossterm::terminal;
+use crossterm::QueueableCommand;
+use std::io;
+
+const LETTERS: [char; 9] = ['a', 's', 'd', 'f', 'g', 'h', 'j', 'k', 'l'];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Follow,
+    NewTab,
+    Copy,
+    Download,
+}
+
+impl Action {
+    fn name(self) -> &'static str {
+        match self {
+            Action::Follow => "follow",
+            Action::NewTab => "new tab",
+            Action::Copy => "copy url",
+            Action::Download => "download",
+        }
+    }
+}
+
+// n labels, all as long as it takes. the first letter changes fastest, so
+// the first key typed rules out the most.
+pub fn labels(n: usize) -> Vec<String> {
+    let mut len = 1;
+    while LETTERS.len().pow(len) < n {
+        len += 1;
+    }
+    (0..n)
+        .map(|i| {
+            (0..len)
+                .map(|digit| LETTERS[i / LETTERS.len().pow(digit) % LETTERS.len()])
+                .collect()
+        })
+        .collect()
+}
+
+// labels the links of page on screen, scrolled to line, until one is
+// picked. a number and enter picks the anchor with that number, on screen
+// or not, and an upper case label opens it in a new tab.
+pub fn pick(page: &Page, line: u16, mut action: Action) -> io::Result<Option<(usize, Action)>> {
+    let (width, height) = terminal::size()?;
+    let (width, height) = (usize::from(width).max(1), usize::from(height));
+    let shown = pager::layout(&page.buf, line, width, height);
+    // the anchors on screen, and the row and column they are at
+    let on_screen: Vec<(usize, u16, u16)> = page
+        .anchor_spans
+        .iter()
+        .enumerate()
+        .filter_map(|(i, span)| {
+            let (row, column) = pager::place(&shown, height, span.start)?;
+            Some((i, row, column))
+        })
+        .collect();
+    let labels = labels(on_screen.len());
+    let mut typed = String::new();
+    loop {
+        pager::pager(&page.buf, line)?;
+        draw_labels(&on_screen, &labels, &typed)?;
+        let hint = match on_screen.is_empty() {
+            true => "no links on screen, type a number",
+            false => "^t new tab, ^y copy, ^w download",
+        };
+        pager::status(&format!("{}: {}  ({})", action.name(), typed, hint))?;
+
+        let Event::Key(k) = read()? else { continue };
+        let numbered = !typed.is_empty() && typed.bytes().all(|b| b.is_ascii_digit());
+        match keys::normalize(k.modifiers, k.code) {
+            (_, KeyCode::Esc) | (KeyModifiers::CONTROL, KeyCode::Char('c')) => return Ok(None),
+            (KeyModifiers::CONTROL, KeyCode::Char(c @ ('t' | 'y' | 'w'))) => {
+                let picked = match c {
+                    't' => Action::NewTab,
+                    'y' => Action::Copy,
+                    _ => Action::Download,
+                };
+                // a second time goes back to following
+                action = match action == picked {
+                    true => Action::Follow,
+                    false => picked,
+                };
+            }
+            (_, KeyCode::Backspace) | (KeyModifiers::CONTROL, KeyCode::Char('h')) => {
+                typed.pop();
+            }
+            (_, KeyCode::Enter) | (KeyModifiers::CONTROL, KeyCode::Char('j' | 'm')) => {
+                if let Some(i) = typed.parse().ok().filter(|&i| i < page.anchors.len()) {
+                    return Ok(Some((i, action)));
+                }
+                // otherwise the first link the label typed so far fits
+                let first = labels.iter().position(|l| l.starts_with(&typed));
+                if let Some(n) = first.filter(|_| !numbered) {
+                    return Ok(Some((on_screen[n].0, action)));
+                }
+            }
+            (_, KeyCode::Char(c)) if c.is_ascii_digit() && (numbered || typed.is_empty()) => {
+                typed.push(c)
+            }
+            (_, KeyCode::Char(c)) if LETTERS.contains(&c.to_ascii_lowercase()) && !numbered => {
+                typed.push(c.to_ascii_lowercase());
+                let fits: Vec<usize> = (0..labels.len())
+                    .filter(|&n| labels[n].starts_with(&typed))
+                    .collect();
+                match fits[..] {
+                    [] => {
+                        typed.pop();
+                    }
+                    [n] if labels[n] == typed => {
+                        if c.is_ascii_uppercase() && action == Action::Follow {
+                            action = Action::NewTab;
+                        }
+                        return Ok(Some((on_screen[n].0, action)));
+                    }
+                    _ => (),
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+// the labels that start with typed, over the start of their links
+fn draw_labels(on_screen: &[(usize, u16, u16)], labels: &[String], typed: &str) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    stdout.queue(cursor::SavePosition)?;
+    for (&(_, row, column), label) in on_screen.iter().zip(labels) {
+        if !label.starts_with(typed) {
+            continue;
+        }
+        stdout.queue(cursor::MoveTo(column, row))?;
+        match config::theme().hint {
+            Some(color) => print!("{}", label.as_str().black().on(color)),
+            None => print!("{}", label.as_str().reverse()),
+        }
+    }
+    stdout.queue(cursor::RestorePosition)?;
+    io::Write::flush(&mut stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn labels_are_as_short_as_they_can_be() {
+        assert!(labels(0).is_empty());
+        assert_eq!(labels(1), ["a"]);
+        assert_eq!(labels(9).concat(), "asdfghjkl");
+        assert_eq!(labels(81).iter().map(String::len).max(), Some(2));
+        for n in [10, 81, 82, 800] {
+            let labels = labels(n);
+            let len = labels[0].len();
+            assert!(labels.iter().all(|l| l.len() == len));
+            assert_eq!(labels.iter().collect::<HashSet<_>>().len(), n);
+        }
+        assert_eq!(labels(82)[0], "aaa");
+    }
+
+    #[test]
+    fn the_first_letter_changes_fastest() {
+        let labels = labels(12);
+        assert_eq!(&labels[..3], ["aa", "sa", "da"]);
+        assert_eq!(&labels[8..], ["la", "as", "ss", "ds"]);
+    }
+}
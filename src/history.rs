@@ -1,25 +1,49 @@
 use crate::page::Page;
 
+// a page in the history, with where it was scrolled to. filled in fields
+// stay with the page itself.
+struct Entry {
+    page: Page,
+    line: u16,
+}
+
 pub struct History {
-    pages: Vec<Page>,
+    entries: Vec<Entry>,
     head: usize,
 }
 
 impl History {
     pub fn new() -> History {
-        let mut pages = Vec::new();
-        pages.push(Page::new());
-        History { pages, head: 0 }
+        History {
+            entries: vec![Entry {
+                page: Page::new(),
+                line: 0,
+            }],
+            head: 0,
+        }
     }
 
     pub fn current(&self) -> &Page {
-        &self.pages[self.head]
+        &self.entries[self.head].page
+    }
+
+    pub fn current_mut(&mut self) -> &mut Page {
+        &mut self.entries[self.head].page
+    }
+
+    // where the current page was scrolled to when it was left
+    pub fn line(&self) -> u16 {
+        self.entries[self.head].line
+    }
+
+    pub fn set_line(&mut self, line: u16) {
+        self.entries[self.head].line = line;
     }
 
     pub fn push(&mut self, page: Page) {
         self.head += 1;
-        self.pages.truncate(self.head);
-        self.pages.push(page);
+        self.entries.truncate(self.head);
+        self.entries.push(Entry { page, line: 0 });
     }
 
     pub fn prev(&mut self) {
@@ -29,7 +53,7 @@ impl History {
     }
 
     pub fn next(&mut self) {
-        if self.head < self.pages.len() - 1 {
+        if self.head < self.entries.len() - 1 {
             self.head += 1;
         }
     }
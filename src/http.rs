@@ -0,0 +1,1025 @@
+// http/1.1 client. follows redirects, understands chunked and compressed
+// bodies, and keeps one idle connection per origin around for reuse.
+
+use crate::cookie::SharedJar;
+use crate::fetch::Settings;
+use crate::url::Url;
+use flate2::write::{DeflateDecoder, MultiGzDecoder, ZlibDecoder};
+use rustls::pki_types::ServerName;
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::Duration;
+
+const MAX_REDIRECTS: usize = 10;
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+// receives a response as it comes in. redirects are followed before head()
+// is called, so it only ever sees the final response.
+pub trait Sink {
+    fn head(&mut self, status: u16, headers: &[(String, String)], url: &Url);
+    // returning false aborts the transfer
+    fn data(&mut self, data: &[u8]) -> bool;
+}
+
+pub fn aborted() -> io::Error {
+    io::Error::new(io::ErrorKind::ConnectionAborted, "aborted")
+}
+
+struct Discard;
+
+impl Sink for Discard {
+    fn head(&mut self, _status: u16, _headers: &[(String, String)], _url: &Url) {}
+
+    fn data(&mut self, _data: &[u8]) -> bool {
+        true
+    }
+}
+
+enum Stream {
+    Plain(TcpStream),
+    Tls(Box<StreamOwned<ClientConnection, TcpStream>>),
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(s) => s.read(buf),
+            Stream::Tls(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(s) => s.write(buf),
+            Stream::Tls(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Plain(s) => s.flush(),
+            Stream::Tls(s) => s.flush(),
+        }
+    }
+}
+
+type Connection = BufReader<Stream>;
+
+struct Target {
+    tls: bool,
+    host: String,
+    port: u16,
+    path: String,
+}
+
+impl Target {
+    fn new(url: &Url) -> io::Result<Target> {
+        let tls = match url.scheme() {
+            "http" => false,
+            "https" => true,
+            _ => return Err(invalid(format!("unsupported url: {}", url))),
+        };
+        Ok(Target {
+            tls,
+            host: url.host().unwrap_or_default().to_string(),
+            port: url.port_or_default().unwrap_or(80),
+            path: url.request_target(),
+        })
+    }
+
+    fn origin(&self) -> String {
+        let scheme = if self.tls { "https" } else { "http" };
+        format!("{}://{}:{}", scheme, self.host, self.port)
+    }
+
+    fn host_header(&self) -> String {
+        match (self.tls, self.port) {
+            (true, 443) | (false, 80) => self.host.clone(),
+            _ => format!("{}:{}", self.host, self.port),
+        }
+    }
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+// takes the set-cookie headers of a response into the jar
+pub fn store_cookies(cookies: &SharedJar, url: &Url, headers: &[(String, String)]) {
+    let mut jar = cookies.lock().unwrap();
+    for (name, value) in headers {
+        if name.eq_ignore_ascii_case("set-cookie") {
+            jar.store(url, value);
+        }
+    }
+}
+
+fn bad_response(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+pub struct Client {
+    user_agent: String,
+    // the http proxy to go through, as host and port
+    proxy: Option<(String, u16)>,
+    headers: Vec<(String, String)>,
+    cookies: SharedJar,
+    idle: HashMap<String, Connection>,
+    tls_config: Option<Arc<ClientConfig>>,
+}
+
+impl Client {
+    pub fn new(settings: &Settings, cookies: SharedJar) -> Client {
+        let proxy = settings.proxy.as_ref().map(|url| {
+            let host = url.host().unwrap_or_default().to_string();
+            (host, url.port_or_default().unwrap_or(80))
+        });
+        Client {
+            user_agent: settings.user_agent.clone(),
+            proxy,
+            headers: settings.headers.clone(),
+            cookies,
+            idle: HashMap::new(),
+            tls_config: None,
+        }
+    }
+
+    // the same client without the connections this one has open
+    pub fn without_connections(&self) -> Client {
+        Client {
+            user_agent: self.user_agent.clone(),
+            proxy: self.proxy.clone(),
+            headers: self.headers.clone(),
+            cookies: self.cookies.clone(),
+            idle: HashMap::new(),
+            tls_config: self.tls_config.clone(),
+        }
+    }
+
+    pub fn request(
+        &mut self,
+        method: &str,
+        url: &Url,
+        headers: &[(&str, &str)],
+        body: &[u8],
+        sink: &mut dyn Sink,
+    ) -> io::Result<()> {
+        let mut method = method.to_string();
+        let mut headers = headers.to_vec();
+        let mut body = body;
+        let mut url = url.clone();
+
+        for _ in 0..=MAX_REDIRECTS {
+            let target = Target::new(&url)?;
+            // every hop gets the cookies for its own url
+            let cookie = self.cookies.lock().unwrap().header(&url);
+            let mut hop_headers = headers.clone();
+            if let Some(cookie) = &cookie {
+                hop_headers.push(("Cookie", cookie));
+            }
+            let (conn, head) = self.send(&target, &method, &hop_headers, body)?;
+            store_cookies(&self.cookies, &url, &head.headers);
+            let location = match head.status {
+                301 | 302 | 303 | 307 | 308 => head.header("location").map(str::to_string),
+                _ => None,
+            };
+            let Some(location) = location else {
+                sink.head(head.status, &head.headers, &url);
+                return self.finish(target.origin(), conn, &method, &head, sink);
+            };
+            self.finish(target.origin(), conn, &method, &head, &mut Discard)?;
+            url = url.join(&location)?;
+            // only 307 and 308 keep the method and the body
+            if !matches!(head.status, 307 | 308) && method != "HEAD" {
+                method = "GET".to_string();
+                body = &[];
+                headers.retain(|(n, _)| !n.eq_ignore_ascii_case("content-type"));
+            }
+        }
+        Err(io::Error::other(format!("too many redirects: {}", url)))
+    }
+
+    fn send(
+        &mut self,
+        target: &Target,
+        method: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> io::Result<(Connection, Head)> {
+        // an idle connection may have been closed by the server meanwhile.
+        // when it was, before any of an answer came back, a request that is
+        // safe to send twice gets a second try on a fresh one.
+        if let Some(mut conn) = self.idle.remove(&target.origin()) {
+            let again = matches!(method, "GET" | "HEAD");
+            match self.write_request(&mut conn, target, method, headers, body) {
+                Err(_) if again => (),
+                Err(e) => return Err(e),
+                Ok(()) => match conn.fill_buf() {
+                    Ok([]) if again => (),
+                    Err(e) if again && was_closed(&e) => (),
+                    _ => return read_response(&mut conn).map(|head| (conn, head)),
+                },
+            }
+        }
+        let mut conn = self.connect(target)?;
+        self.write_request(&mut conn, target, method, headers, body)?;
+        let head = read_response(&mut conn)?;
+        Ok((conn, head))
+    }
+
+    fn finish(
+        &mut self,
+        origin: String,
+        mut conn: Connection,
+        method: &str,
+        head: &Head,
+        sink: &mut dyn Sink,
+    ) -> io::Result<()> {
+        let framed = read_body(&mut conn, head, method, sink)?;
+        if head.keep_alive && framed {
+            self.idle.insert(origin, conn);
+        }
+        Ok(())
+    }
+
+    fn write_request(
+        &self,
+        conn: &mut Connection,
+        target: &Target,
+        method: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> io::Result<()> {
+        // a proxy is asked for the whole url, unless it tunnels to the server
+        let path = match (&self.proxy, target.tls) {
+            (Some(_), false) => format!("http://{}{}", target.host_header(), target.path),
+            _ => target.path.clone(),
+        };
+        let mut req = format!("{} {} HTTP/1.1\r\n", method, path);
+        req.push_str(&format!("Host: {}\r\n", target.host_header()));
+        // the headers of the settings go in place of the built in ones
+        let own = [
+            ("User-Agent", self.user_agent.as_str()),
+            ("Accept", "*/*"),
+            ("Accept-Encoding", "gzip, deflate"),
+            ("Connection", "keep-alive"),
+        ];
+        for (name, value) in own {
+            if !self
+                .headers
+                .iter()
+                .any(|(n, _)| n.eq_ignore_ascii_case(name))
+            {
+                req.push_str(&format!("{}: {}\r\n", name, value));
+            }
+        }
+        for (name, value) in &self.headers {
+            req.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        for (name, value) in headers {
+            req.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        if !body.is_empty() || method == "POST" {
+            req.push_str(&format!("Content-Length: {}\r\n", body.len()));
+        }
+        req.push_str("\r\n");
+
+        let stream = conn.get_mut();
+        stream.write_all(req.as_bytes())?;
+        stream.write_all(body)?;
+        stream.flush()
+    }
+
+    fn connect(&mut self, target: &Target) -> io::Result<Connection> {
+        let host = target.host.trim_start_matches('[').trim_end_matches(']');
+        let (addr_host, addr_port) = match &self.proxy {
+            Some((proxy, port)) => (proxy.trim_start_matches('[').trim_end_matches(']'), *port),
+            None => (host, target.port),
+        };
+        let mut last_err = io::Error::other(format!("could not resolve {}", addr_host));
+        let mut tcp = None;
+        for addr in (addr_host, addr_port).to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, TIMEOUT) {
+                Ok(s) => {
+                    tcp = Some(s);
+                    break;
+                }
+                Err(e) => last_err = e,
+            }
+        }
+        let tcp = tcp.ok_or(last_err)?;
+        tcp.set_read_timeout(Some(TIMEOUT))?;
+        tcp.set_write_timeout(Some(TIMEOUT))?;
+
+        if !target.tls {
+            return Ok(BufReader::new(Stream::Plain(tcp)));
+        }
+        let tcp = match self.proxy {
+            Some(_) => tunnel(tcp, target)?,
+            None => tcp,
+        };
+        let name = ServerName::try_from(host.to_string())
+            .map_err(|_| invalid(format!("bad host name: {}", host)))?;
+        let tls = ClientConnection::new(self.tls_config(), name).map_err(io::Error::other)?;
+        let stream = StreamOwned::new(tls, tcp);
+        Ok(BufReader::new(Stream::Tls(Box::new(stream))))
+    }
+
+    fn tls_config(&mut self) -> Arc<ClientConfig> {
+        self.tls_config
+            .get_or_insert_with(|| {
+                let mut roots = RootCertStore::empty();
+                roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+                let provider = Arc::new(rustls::crypto::ring::default_provider());
+                let config = ClientConfig::builder_with_provider(provider)
+                    .with_safe_default_protocol_versions()
+                    .expect("tls protocol versions")
+                    .with_root_certificates(roots)
+                    .with_no_client_auth();
+                Arc::new(config)
+            })
+            .clone()
+    }
+}
+
+// asks the proxy for a tunnel to the server, for tls to go through
+fn tunnel(tcp: TcpStream, target: &Target) -> io::Result<TcpStream> {
+    let authority = format!("{}:{}", target.host, target.port);
+    let mut conn = BufReader::new(Stream::Plain(tcp));
+    let req = format!(
+        "CONNECT {} HTTP/1.1\r\nHost: {}\r\n\r\n",
+        authority, authority
+    );
+    conn.get_mut().write_all(req.as_bytes())?;
+    let head = read_head(&mut conn)?;
+    if head.status != 200 {
+        return Err(io::Error::other(format!(
+            "proxy refused to connect to {}: {}",
+            authority, head.status
+        )));
+    }
+    // the server speaks first only once tls starts, so nothing is buffered
+    match conn.into_inner() {
+        Stream::Plain(tcp) => Ok(tcp),
+        Stream::Tls(_) => unreachable!(),
+    }
+}
+
+struct Head {
+    keep_alive: bool,
+    status: u16,
+    headers: Vec<(String, String)>,
+}
+
+impl Head {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+fn read_line(conn: &mut Connection) -> io::Result<String> {
+    let mut line = Vec::new();
+    if conn.read_until(b'\n', &mut line)? == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "connection closed",
+        ));
+    }
+    // header values are supposed to be ascii, latin-1 keeps anything else
+    let line: String = line.iter().map(|&b| b as char).collect();
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+fn read_response(conn: &mut Connection) -> io::Result<Head> {
+    loop {
+        let head = read_head(conn)?;
+        // interim responses like 100 continue are followed by the real one
+        if !(100..200).contains(&head.status) {
+            return Ok(head);
+        }
+    }
+}
+
+// the other end closed the connection, rather than being slow
+fn was_closed(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::BrokenPipe
+            | io::ErrorKind::UnexpectedEof
+    )
+}
+
+fn read_head(conn: &mut Connection) -> io::Result<Head> {
+    let status_line = read_line(conn)?;
+    let mut parts = status_line.splitn(3, ' ');
+    let version = parts.next().unwrap_or_default();
+    if !version.starts_with("HTTP/") {
+        return Err(bad_response("not an http response"));
+    }
+    let status = parts
+        .next()
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| bad_response("bad status line"))?;
+
+    let mut headers: Vec<(String, String)> = Vec::new();
+    loop {
+        let line = read_line(conn)?;
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+            continue;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    let connection = headers
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case("connection"))
+        .map_or("", |(_, v)| v.as_str());
+    let keep_alive = match version {
+        "HTTP/1.0" => connection.eq_ignore_ascii_case("keep-alive"),
+        _ => !connection.eq_ignore_ascii_case("close"),
+    };
+    Ok(Head {
+        keep_alive,
+        status,
+        headers,
+    })
+}
+
+// passes the body to the sink, returns whether it had a known length so
+// the connection can be reused afterwards
+fn read_body(
+    conn: &mut Connection,
+    head: &Head,
+    method: &str,
+    sink: &mut dyn Sink,
+) -> io::Result<bool> {
+    if method == "HEAD" || head.status == 204 || head.status == 304 {
+        return Ok(true);
+    }
+    let encoding = head.header("content-encoding").unwrap_or_default();
+    let mut out = BodyWriter::new(encoding, sink);
+    let mut buf = [0u8; 16 * 1024];
+    let chunked = head
+        .header("transfer-encoding")
+        .is_some_and(|te| te.to_ascii_lowercase().contains("chunked"));
+
+    let framed = if chunked {
+        loop {
+            let line = read_line(conn)?;
+            let size = line.split(';').next().unwrap_or_default().trim();
+            let size =
+                usize::from_str_radix(size, 16).map_err(|_| bad_response("bad chunk size"))?;
+            if size == 0 {
+                // trailers, up to the empty line
+                while !read_line(conn)?.is_empty() {}
+                break true;
+            }
+            copy_exact(conn, size, &mut out, &mut buf)?;
+            read_line(conn)?;
+        }
+    } else if let Some(len) = head.header("content-length") {
+        let len = len
+            .parse()
+            .map_err(|_| bad_response("bad content-length"))?;
+        copy_exact(conn, len, &mut out, &mut buf)?;
+        true
+    } else {
+        loop {
+            let n = conn.read(&mut buf)?;
+            if n == 0 {
+                break false;
+            }
+            out.write(&buf[..n])?;
+        }
+    };
+    out.finish()?;
+    Ok(framed)
+}
+
+fn copy_exact(
+    conn: &mut Connection,
+    mut len: usize,
+    out: &mut BodyWriter,
+    buf: &mut [u8],
+) -> io::Result<()> {
+    while len > 0 {
+        let want = len.min(buf.len());
+        let n = conn.read(&mut buf[..want])?;
+        if n == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "connection closed in the middle of the body",
+            ));
+        }
+        out.write(&buf[..n])?;
+        len -= n;
+    }
+    Ok(())
+}
+
+struct SinkWriter<'a>(&'a mut dyn Sink);
+
+impl Write for SinkWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.0.data(buf) {
+            true => Ok(buf.len()),
+            false => Err(aborted()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+enum Decode<'a> {
+    Identity(SinkWriter<'a>),
+    Gzip(MultiGzDecoder<SinkWriter<'a>>),
+    Zlib(ZlibDecoder<SinkWriter<'a>>),
+    Deflate(DeflateDecoder<SinkWriter<'a>>),
+}
+
+// undoes the content-encoding on the way to the sink. the decoder is picked
+// on the first write, since raw and zlib-wrapped deflate look the same
+// from the headers.
+struct BodyWriter<'a> {
+    encoding: String,
+    sink: Option<SinkWriter<'a>>,
+    decode: Option<Decode<'a>>,
+}
+
+impl<'a> BodyWriter<'a> {
+    fn new(encoding: &str, sink: &'a mut dyn Sink) -> BodyWriter<'a> {
+        BodyWriter {
+            encoding: encoding.trim().to_ascii_lowercase(),
+            sink: Some(SinkWriter(sink)),
+            decode: None,
+        }
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        let decode = match &mut self.decode {
+            Some(decode) => decode,
+            None => {
+                let sink = self.sink.take().unwrap();
+                let decode = match self.encoding.as_str() {
+                    "gzip" | "x-gzip" => Decode::Gzip(MultiGzDecoder::new(sink)),
+                    "deflate" if is_zlib(data) => Decode::Zlib(ZlibDecoder::new(sink)),
+                    "deflate" => Decode::Deflate(DeflateDecoder::new(sink)),
+                    _ => Decode::Identity(sink),
+                };
+                self.decode.insert(decode)
+            }
+        };
+        match decode {
+            Decode::Identity(w) => w.write_all(data),
+            Decode::Gzip(d) => d.write_all(data),
+            Decode::Zlib(d) => d.write_all(data),
+            Decode::Deflate(d) => d.write_all(data),
+        }
+    }
+
+    fn finish(self) -> io::Result<()> {
+        match self.decode {
+            Some(Decode::Gzip(d)) => d.finish().map(drop),
+            Some(Decode::Zlib(d)) => d.finish().map(drop),
+            Some(Decode::Deflate(d)) => d.finish().map(drop),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn is_zlib(data: &[u8]) -> bool {
+    match data {
+        [cmf, flg, ..] => cmf & 0x0F == 8 && (u16::from(*cmf) << 8 | u16::from(*flg)) % 31 == 0,
+        _ => true,
+    }
+}
+
+// the loopback server is also used by the tests of what goes in requests
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::cookie::Jar;
+    use flate2::write::{DeflateEncoder, GzEncoder, ZlibEncoder};
+    use flate2::Compression;
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{mpsc, Mutex};
+    use std::thread;
+
+    #[derive(Default)]
+    pub(crate) struct Collect {
+        status: u16,
+        url: String,
+        body: Vec<u8>,
+    }
+
+    impl Sink for Collect {
+        fn head(&mut self, status: u16, _headers: &[(String, String)], url: &Url) {
+            self.status = status;
+            self.url = url.to_string();
+        }
+
+        fn data(&mut self, data: &[u8]) -> bool {
+            self.body.extend_from_slice(data);
+            true
+        }
+    }
+
+    // the headers and body of a request, as the server got them
+    pub(crate) struct Received {
+        pub headers: Vec<(String, String)>,
+        pub body: Vec<u8>,
+    }
+
+    impl Received {
+        pub fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find(|(n, _)| n.eq_ignore_ascii_case(name))
+                .map(|(_, v)| v.as_str())
+        }
+    }
+
+    type Requests<T> = Arc<Mutex<Vec<T>>>;
+
+    // a server on a loopback port that answers each request line with what
+    // respond makes of it, on as many connections as it is sent. returns
+    // its port, how many connections it took and the request lines it got.
+    fn serve(respond: fn(&str) -> Vec<u8>) -> (u16, Arc<AtomicUsize>, Requests<String>) {
+        let (port, connections, requests, _) = listen(respond);
+        (port, connections, requests)
+    }
+
+    // the same, with the whole of the requests it got
+    pub(crate) fn serve_received(respond: fn(&str) -> Vec<u8>) -> (u16, Requests<Received>) {
+        let (port, _, _, received) = listen(respond);
+        (port, received)
+    }
+
+    fn listen(
+        respond: fn(&str) -> Vec<u8>,
+    ) -> (u16, Arc<AtomicUsize>, Requests<String>, Requests<Received>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let (count, seen, got) = (connections.clone(), requests.clone(), received.clone());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                count.fetch_add(1, Ordering::SeqCst);
+                let (seen, got) = (seen.clone(), got.clone());
+                thread::spawn(move || answer(stream.unwrap(), respond, &seen, &got));
+            }
+        });
+        (port, connections, requests, received)
+    }
+
+    fn answer(
+        stream: TcpStream,
+        respond: fn(&str) -> Vec<u8>,
+        seen: &Mutex<Vec<String>>,
+        got: &Mutex<Vec<Received>>,
+    ) {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut stream = stream;
+        loop {
+            let mut request = String::new();
+            if reader.read_line(&mut request).unwrap_or(0) == 0 {
+                return;
+            }
+            let mut len = 0;
+            let mut headers = Vec::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        len = value.trim().parse().unwrap();
+                    }
+                    headers.push((name.to_string(), value.trim().to_string()));
+                }
+            }
+            let mut body = vec![0; len];
+            reader.read_exact(&mut body).unwrap();
+            let request = request.trim().to_string();
+            seen.lock().unwrap().push(request.clone());
+            got.lock().unwrap().push(Received { headers, body });
+            let response = respond(&request);
+            // nothing to say hangs up without an answer
+            if response.is_empty() {
+                return;
+            }
+            stream.write_all(&response).unwrap();
+            // x-hang-up hangs up after it, without telling the client
+            let response = String::from_utf8_lossy(&response);
+            if response.contains("Connection: close") || response.contains("X-Hang-Up") {
+                return;
+            }
+        }
+    }
+
+    pub(crate) fn client(proxy: Option<u16>) -> Client {
+        let settings = Settings {
+            user_agent: "test".to_string(),
+            proxy: proxy.map(|port| Url::parse(&format!("http://127.0.0.1:{}", port)).unwrap()),
+            headers: Vec::new(),
+        };
+        Client::new(&settings, Jar::load(None).shared())
+    }
+
+    fn get(client: &mut Client, port: u16, path: &str) -> io::Result<Collect> {
+        let url = Url::parse(&format!("http://127.0.0.1:{}{}", port, path)).unwrap();
+        let mut sink = Collect::default();
+        client.request("GET", &url, &[], &[], &mut sink)?;
+        Ok(sink)
+    }
+
+    pub(crate) fn response(head: &str, body: &[u8]) -> Vec<u8> {
+        let mut response = format!(
+            "HTTP/1.1 200 OK\r\n{}Content-Length: {}\r\n\r\n",
+            head,
+            body.len()
+        )
+        .into_bytes();
+        response.extend_from_slice(body);
+        response
+    }
+
+    #[test]
+    fn follows_redirects_on_one_connection() {
+        let (port, connections, requests) = serve(|request| match request {
+            "POST /form HTTP/1.1" => {
+                b"HTTP/1.1 303 See Other\r\nLocation: /a\r\nContent-Length: 0\r\n\r\n".to_vec()
+            }
+            "GET /a HTTP/1.1" => {
+                b"HTTP/1.1 302 Found\r\nLocation: b?x=1\r\nContent-Length: 3\r\n\r\nabc".to_vec()
+            }
+            _ => response("", b"done"),
+        });
+        let mut client = client(None);
+        let url = Url::parse(&format!("http://127.0.0.1:{}/form", port)).unwrap();
+        let mut sink = Collect::default();
+        client
+            .request(
+                "POST",
+                &url,
+                &[("Content-Type", "text/plain")],
+                b"hi",
+                &mut sink,
+            )
+            .unwrap();
+        assert_eq!(sink.status, 200);
+        assert_eq!(sink.url, format!("http://127.0.0.1:{}/b?x=1", port));
+        assert_eq!(sink.body, b"done");
+        // the 303 turns the post into a get
+        assert_eq!(
+            *requests.lock().unwrap(),
+            [
+                "POST /form HTTP/1.1",
+                "GET /a HTTP/1.1",
+                "GET /b?x=1 HTTP/1.1"
+            ]
+        );
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn gives_up_on_a_redirect_loop() {
+        let (port, _, requests) = serve(|_| {
+            b"HTTP/1.1 302 Found\r\nLocation: /loop\r\nContent-Length: 0\r\n\r\n".to_vec()
+        });
+        let err = get(&mut client(None), port, "/loop").err().unwrap();
+        assert!(err.to_string().starts_with("too many redirects"), "{}", err);
+        assert_eq!(requests.lock().unwrap().len(), MAX_REDIRECTS + 1);
+    }
+
+    #[test]
+    fn decodes_chunked_bodies() {
+        let (port, _, _) = serve(|_| {
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+              5\r\nhello\r\n6;name=value\r\n world\r\n0\r\nTrailer: x\r\n\r\n"
+                .to_vec()
+        });
+        let mut client = client(None);
+        assert_eq!(get(&mut client, port, "/").unwrap().body, b"hello world");
+        // the trailers were read, so the connection is ready for another
+        assert_eq!(get(&mut client, port, "/").unwrap().body, b"hello world");
+    }
+
+    #[test]
+    fn fails_on_a_truncated_chunk() {
+        let (port, _, _) = serve(|_| {
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n\
+              5\r\nhello\r\na\r\nhel"
+                .to_vec()
+        });
+        let err = get(&mut client(None), port, "/").err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn fails_on_a_bad_chunk_size() {
+        let (port, _, _) =
+            serve(|_| b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n".to_vec());
+        let err = get(&mut client(None), port, "/").err().unwrap();
+        assert_eq!(err.to_string(), "bad chunk size");
+    }
+
+    fn compressed(encoding: &str, text: &[u8]) -> Vec<u8> {
+        let body = match encoding {
+            "gzip" => {
+                let mut e = GzEncoder::new(Vec::new(), Compression::default());
+                e.write_all(text).unwrap();
+                e.finish().unwrap()
+            }
+            "zlib" => {
+                let mut e = ZlibEncoder::new(Vec::new(), Compression::default());
+                e.write_all(text).unwrap();
+                e.finish().unwrap()
+            }
+            _ => {
+                let mut e = DeflateEncoder::new(Vec::new(), Compression::default());
+                e.write_all(text).unwrap();
+                e.finish().unwrap()
+            }
+        };
+        // zlib wrapped and raw deflate both go as deflate
+        let name = match encoding {
+            "gzip" => "gzip",
+            _ => "deflate",
+        };
+        response(&format!("Content-Encoding: {}\r\n", name), &body)
+    }
+
+    #[test]
+    fn decompresses_bodies() {
+        let (port, _, _) = serve(|request| {
+            let text = b"a compressed body, a compressed body";
+            match request {
+                "GET /gzip HTTP/1.1" => compressed("gzip", text),
+                "GET /zlib HTTP/1.1" => compressed("zlib", text),
+                _ => compressed("deflate", text),
+            }
+        });
+        let mut client = client(None);
+        for path in ["/gzip", "/zlib", "/deflate"] {
+            let body = get(&mut client, port, path).unwrap().body;
+            assert_eq!(body, b"a compressed body, a compressed body", "{}", path);
+        }
+    }
+
+    #[test]
+    fn reuses_kept_alive_connections() {
+        let (port, connections, _) = serve(|request| match request {
+            "GET /close HTTP/1.1" => response("Connection: close\r\n", b"bye"),
+            _ => response("", b"hi"),
+        });
+        let mut client = client(None);
+        for _ in 0..3 {
+            assert_eq!(get(&mut client, port, "/").unwrap().body, b"hi");
+        }
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+        get(&mut client, port, "/close").unwrap();
+        get(&mut client, port, "/").unwrap();
+        assert_eq!(connections.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn tries_a_get_again_when_an_idle_connection_was_closed() {
+        let (port, connections, requests) = serve(|_| response("X-Hang-Up: 1\r\n", b"hi"));
+        let mut client = client(None);
+        get(&mut client, port, "/a").unwrap();
+        // the server has closed the connection kept for this
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(get(&mut client, port, "/b").unwrap().body, b"hi");
+        assert_eq!(connections.load(Ordering::SeqCst), 2);
+        assert_eq!(
+            *requests.lock().unwrap(),
+            ["GET /a HTTP/1.1", "GET /b HTTP/1.1"]
+        );
+    }
+
+    #[test]
+    fn never_sends_a_post_twice() {
+        let (port, connections, requests) = serve(|request| match request {
+            "POST /form HTTP/1.1" => Vec::new(),
+            _ => response("", b"hi"),
+        });
+        let mut client = client(None);
+        get(&mut client, port, "/").unwrap();
+        let url = Url::parse(&format!("http://127.0.0.1:{}/form", port)).unwrap();
+        let res = client.request("POST", &url, &[], b"a=1", &mut Collect::default());
+        assert!(res.is_err());
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            *requests.lock().unwrap(),
+            ["GET / HTTP/1.1", "POST /form HTTP/1.1"]
+        );
+    }
+
+    #[test]
+    fn reads_to_the_end_without_a_length() {
+        let (port, connections, _) =
+            serve(|_| b"HTTP/1.0 200 OK\r\nConnection: close\r\n\r\nall of it".to_vec());
+        let mut client = client(None);
+        assert_eq!(get(&mut client, port, "/").unwrap().body, b"all of it");
+        get(&mut client, port, "/").unwrap();
+        assert_eq!(connections.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn asks_a_proxy_for_the_whole_url() {
+        let (port, _, requests) = serve(|_| response("", b"proxied"));
+        let mut client = client(Some(port));
+        let url = Url::parse("http://example.com:8080/a?b").unwrap();
+        let mut sink = Collect::default();
+        client.request("GET", &url, &[], &[], &mut sink).unwrap();
+        assert_eq!(sink.body, b"proxied");
+        assert_eq!(
+            *requests.lock().unwrap(),
+            ["GET http://example.com:8080/a?b HTTP/1.1"]
+        );
+    }
+
+    // a proxy that answers a connect with status and sends back what it
+    // got, and the first byte after it
+    fn connect_proxy(status: &'static str) -> (u16, mpsc::Receiver<(String, Option<u8>)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut head = String::new();
+            while !head.ends_with("\r\n\r\n") {
+                if reader.read_line(&mut head).unwrap() == 0 {
+                    break;
+                }
+            }
+            let mut stream = stream;
+            stream.write_all(status.as_bytes()).unwrap();
+            let mut first = [0];
+            let first = reader.read_exact(&mut first).ok().map(|_| first[0]);
+            tx.send((head, first)).unwrap();
+        });
+        (port, rx)
+    }
+
+    #[test]
+    fn tunnels_tls_through_a_proxy() {
+        let (port, rx) = connect_proxy("HTTP/1.1 200 Connection established\r\n\r\n");
+        let url = Url::parse("https://example.com/").unwrap();
+        // the proxy goes away after the first byte, so the handshake fails
+        let _ = client(Some(port)).request("GET", &url, &[], &[], &mut Collect::default());
+        let (head, first) = rx.recv().unwrap();
+        assert!(
+            head.starts_with("CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\n"),
+            "{}",
+            head
+        );
+        // a tls handshake record
+        assert_eq!(first, Some(0x16));
+    }
+
+    #[test]
+    fn reports_a_refused_tunnel() {
+        let (port, _rx) = connect_proxy("HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\n\r\n");
+        let url = Url::parse("https://example.com/").unwrap();
+        let err = client(Some(port))
+            .request("GET", &url, &[], &[], &mut Collect::default())
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "proxy refused to connect to example.com:443: 403"
+        );
+    }
+}
@@ -4,14 +4,47 @@ use std::time::Duration;
 
 use crossterm::cursor;
 use crossterm::event::{poll, read, Event, KeyCode, KeyModifiers};
-use crossterm::terminal::{Clear, ClearType};
+use crossterm::style::Stylize;
+use crossterm::terminal::{self, Clear, ClearType};
 use crossterm::{ExecutableCommand, QueueableCommand};
 
+// gives the ways what is typed so far could go on
+pub type Complete<'a> = &'a dyn Fn(&str) -> Vec<String>;
+
 pub fn get_input(prompt: &str) -> io::Result<String> {
     get_input_with(prompt, None)
 }
 
 pub fn get_input_with(prompt: &str, start_val: Option<&str>) -> io::Result<String> {
+    read_input(prompt, start_val, None, &[])
+}
+
+// tab goes through the completions of what was typed, instead of listing
+// files
+pub fn get_input_completing(
+    prompt: &str,
+    start_val: Option<&str>,
+    complete: Complete,
+) -> io::Result<String> {
+    read_input(prompt, start_val, Some(complete), &[])
+}
+
+// up and down (or ^p and ^n) go through the lines given before, oldest
+// first, as well
+pub fn get_input_history(
+    prompt: &str,
+    complete: Complete,
+    history: &[String],
+) -> io::Result<String> {
+    read_input(prompt, None, Some(complete), history)
+}
+
+fn read_input(
+    prompt: &str,
+    start_val: Option<&str>,
+    complete: Option<Complete>,
+    history: &[String],
+) -> io::Result<String> {
     let mut stdout = io::stdout();
     stdout.execute(cursor::SavePosition)?;
     stdout.execute(cursor::MoveTo(0, 0))?;
@@ -42,7 +75,7 @@ pub fn get_input_with(prompt: &str, start_val: Option<&str>) -> io::Result<Strin
     let bs = |buf: &mut String, stdout: &mut Stdout| -> io::Result<()> {
         let cursor_pos = cursor::position()?.0;
         let remove_pos = cursor_pos - cursor_zero;
-        if remove_pos <= 0 {
+        if remove_pos == 0 {
             return Ok(());
         }
         buf.remove((remove_pos - 1).into());
@@ -71,9 +104,39 @@ pub fn get_input_with(prompt: &str, start_val: Option<&str>) -> io::Result<Strin
         Ok(())
     };
 
+    let replace = |buf: &mut String, stdout: &mut Stdout, s: &str| -> io::Result<()> {
+        *buf = s.to_string();
+        stdout.queue(cursor::MoveToColumn(cursor_zero))?;
+        stdout.queue(Clear(ClearType::UntilNewLine))?;
+        print!("{}", buf);
+        io::Write::flush(stdout)?;
+        Ok(())
+    };
+
+    // the completions tab goes through, and the one shown
+    let mut completions: Vec<String> = Vec::new();
+    let mut completion = 0;
+    // the line of history shown, and what was typed before going back
+    let mut recalled = history.len();
+    let mut typed = String::new();
+    let mut recall = |buf: &mut String, stdout: &mut Stdout, back: bool| -> io::Result<()> {
+        if recalled == history.len() {
+            typed = buf.clone();
+        }
+        recalled = match back {
+            true => recalled.saturating_sub(1),
+            false => (recalled + 1).min(history.len()),
+        };
+        let line = history.get(recalled).unwrap_or(&typed).clone();
+        replace(buf, stdout, &line)
+    };
+
     loop {
         if poll(Duration::from_millis(1000))? {
             let Event::Key(e) = read()? else { continue };
+            if e.code != KeyCode::Tab {
+                completions.clear();
+            }
             if e.modifiers == KeyModifiers::NONE {
                 match e.code {
                     KeyCode::Char(c) => insert_char(&mut buf, &mut stdout, c)?,
@@ -85,6 +148,36 @@ pub fn get_input_with(prompt: &str, start_val: Option<&str>) -> io::Result<Strin
                         cancel(&mut buf, &mut stdout)?;
                         break;
                     }
+                    KeyCode::Up => recall(&mut buf, &mut stdout, true)?,
+                    KeyCode::Down => recall(&mut buf, &mut stdout, false)?,
+                    KeyCode::Tab if complete.is_some() => {
+                        if completions.is_empty() {
+                            completions = complete.unwrap()(&buf);
+                            completion = 0;
+                        } else {
+                            completion = (completion + 1) % completions.len();
+                        }
+                        let Some(s) = completions.get(completion) else {
+                            continue;
+                        };
+                        replace(&mut buf, &mut stdout, s)?;
+                        let pos = cursor::position()?;
+                        let width = terminal::size()?.0 as usize;
+                        let list: String = completions[completion..]
+                            .iter()
+                            .chain(&completions[..completion])
+                            .map(String::as_str)
+                            .collect::<Vec<_>>()
+                            .join("  ")
+                            .chars()
+                            .take(width)
+                            .collect();
+                        stdout.queue(cursor::MoveTo(0, pos.1 + 1))?;
+                        stdout.queue(Clear(ClearType::CurrentLine))?;
+                        print!("{}", list);
+                        stdout.queue(cursor::MoveTo(pos.0, pos.1))?;
+                        io::Write::flush(&mut stdout)?;
+                    }
                     KeyCode::Tab => {
                         let mut ls = Command::new("ls");
                         let dir = match buf.is_empty() {
@@ -110,6 +203,8 @@ pub fn get_input_with(prompt: &str, start_val: Option<&str>) -> io::Result<Strin
                     KeyCode::Char('h') => {
                         bs(&mut buf, &mut stdout)?;
                     }
+                    KeyCode::Char('p') => recall(&mut buf, &mut stdout, true)?,
+                    KeyCode::Char('n') => recall(&mut buf, &mut stdout, false)?,
                     KeyCode::Char('u') => {
                         buf = buf.get(cursor_pos()? as usize..).unwrap().to_string();
                         stdout.queue(cursor::MoveToColumn(cursor_zero))?;
@@ -118,15 +213,11 @@ pub fn get_input_with(prompt: &str, start_val: Option<&str>) -> io::Result<Strin
                         stdout.queue(cursor::MoveToColumn(cursor_zero))?;
                         io::Write::flush(&mut stdout)?;
                     }
-                    KeyCode::Char('b') => {
-                        if cursor_pos()? > 0 {
-                            stdout.execute(cursor::MoveLeft(1))?;
-                        }
+                    KeyCode::Char('b') if cursor_pos()? > 0 => {
+                        stdout.execute(cursor::MoveLeft(1))?;
                     }
-                    KeyCode::Char('f') => {
-                        if (cursor_pos()? as usize) < buf.len() {
-                            stdout.execute(cursor::MoveRight(1))?;
-                        }
+                    KeyCode::Char('f') if (cursor_pos()? as usize) < buf.len() => {
+                        stdout.execute(cursor::MoveRight(1))?;
                     }
                     KeyCode::Char('a') => {
                         stdout.execute(cursor::MoveToColumn(cursor_zero))?;
@@ -151,3 +242,61 @@ pub fn get_input_with(prompt: &str, start_val: Option<&str>) -> io::Result<Strin
     }
     Ok(buf)
 }
+
+// a list that narrows down as you type. up and down (or ^p and ^n) move
+// the selection and enter picks it. items gives the lines to show for
+// what is typed so far, each with what picking it gives back.
+pub fn pick<T>(prompt: &str, items: impl Fn(&str) -> Vec<(String, T)>) -> io::Result<Option<T>> {
+    let mut stdout = io::stdout();
+    let mut query = String::new();
+    let mut selected = 0usize;
+    loop {
+        let mut found = items(&query);
+        selected = selected.min(found.len().saturating_sub(1));
+
+        let (width, height) = terminal::size()?;
+        let rows = height.saturating_sub(1) as usize;
+        let top = selected.saturating_sub(rows.saturating_sub(1));
+        stdout.queue(cursor::MoveTo(0, 0))?;
+        stdout.queue(Clear(ClearType::All))?;
+        for (row, (line, _)) in found.iter().enumerate().skip(top).take(rows) {
+            let line: String = line.chars().take(width.into()).collect();
+            stdout.queue(cursor::MoveTo(0, (row - top + 1) as u16))?;
+            match row == selected {
+                true => print!("{}", line.reverse()),
+                false => print!("{}", line),
+            }
+        }
+        stdout.queue(cursor::MoveTo(0, 0))?;
+        print!("{}{}", prompt, query);
+        io::Write::flush(&mut stdout)?;
+
+        let Event::Key(e) = read()? else { continue };
+        match (e.modifiers, e.code) {
+            (_, KeyCode::Esc) | (KeyModifiers::CONTROL, KeyCode::Char('c')) => return Ok(None),
+            (_, KeyCode::Enter) | (KeyModifiers::CONTROL, KeyCode::Char('j' | 'm')) => {
+                return Ok(match found.is_empty() {
+                    true => None,
+                    false => Some(found.swap_remove(selected).1),
+                });
+            }
+            (_, KeyCode::Up) | (KeyModifiers::CONTROL, KeyCode::Char('p')) => {
+                selected = selected.saturating_sub(1)
+            }
+            (_, KeyCode::Down) | (KeyModifiers::CONTROL, KeyCode::Char('n')) => selected += 1,
+            (_, KeyCode::Backspace) | (KeyModifiers::CONTROL, KeyCode::Char('h')) => {
+                query.pop();
+                selected = 0;
+            }
+            (KeyModifiers::CONTROL, KeyCode::Char('u')) => {
+                query.clear();
+                selected = 0;
+            }
+            (KeyModifiers::NONE | KeyModifiers::SHIFT, KeyCode::Char(c)) => {
+                query.push(c);
+                selected = 0;
+            }
+            _ => (),
+        }
+    }
+}
@@ -0,0 +1,567 @@
+// what the browser can be told to do, by name, and the keys that do it.
+// a binding is a sequence of keys, like "gg", and the keymap of the config
+// goes on top of the default one.
+
+use crossterm::event::{KeyCode, KeyModifiers};
+use std::fmt::Write;
+
+// a key with its modifiers, as "x", "X", "^x", "M-x" or "esc"
+pub type Key = (KeyModifiers, KeyCode);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Command {
+    Quit,
+    Cancel,
+    ScrollDown,
+    ScrollUp,
+    PageDown,
+    PageUp,
+    Top,
+    Bottom,
+    Back,
+    Forward,
+    Goto,
+    GotoCurrent,
+    Follow,
+    FollowNewTab,
+    FocusNext,
+    FocusPrev,
+    OpenFocused,
+    Search,
+    SearchBack,
+    SearchNext,
+    SearchPrev,
+    OpenFile,
+    PostData,
+    EditField,
+    Submit,
+    Download,
+    PostDownload,
+    Info,
+    Cookies,
+    History,
+    Bookmarks,
+    Bookmark,
+    ManageBookmarks,
+    NewTab,
+    NextTab,
+    PrevTab,
+    CloseTab,
+    ListTabs,
+    Execute,
+    Help,
+}
+
+// every command with its name and what it does, in the order help lists them
+pub const COMMANDS: &[(Command, &str, &str)] = &[
+    (Command::Quit, "quit", "exit, or cancel loading"),
+    (
+        Command::Cancel,
+        "cancel",
+        "cancel loading, the page stays as it was",
+    ),
+    (Command::ScrollDown, "scroll-down", "scroll down a line"),
+    (Command::ScrollUp, "scroll-up", "scroll up a line"),
+    (Command::PageDown, "page-down", "scroll down half a screen"),
+    (Command::PageUp, "page-up", "scroll up half a screen"),
+    (Command::Top, "top", "go to the top of the page"),
+    (Command::Bottom, "bottom", "go to the bottom of the page"),
+    (Command::Back, "back", "back in the history"),
+    (Command::Forward, "forward", "forward in the history"),
+    (Command::Goto, "goto", "go to a url"),
+    (
+        Command::GotoCurrent,
+        "goto-current",
+        "go to a url, starting from this one",
+    ),
+    (
+        Command::Follow,
+        "follow",
+        "label the links on screen, type one to follow it",
+    ),
+    (
+        Command::FollowNewTab,
+        "follow-new-tab",
+        "follow a link in a new tab",
+    ),
+    (
+        Command::FocusNext,
+        "focus-next",
+        "pick out the next link or field on screen",
+    ),
+    (Command::FocusPrev, "focus-prev", "pick out the one before"),
+    (
+        Command::OpenFocused,
+        "open-focused",
+        "follow the link picked out, or edit the field",
+    ),
+    (
+        Command::Search,
+        "search",
+        "search the page down, as you type",
+    ),
+    (Command::SearchBack, "search-back", "search the page up"),
+    (
+        Command::SearchNext,
+        "search-next",
+        "the next match of the search",
+    ),
+    (Command::SearchPrev, "search-prev", "the match before"),
+    (Command::OpenFile, "open-file", "open a local file"),
+    (Command::PostData, "post-data", "send data to this url"),
+    (
+        Command::EditField,
+        "edit-field",
+        "fill in a form field by its number",
+    ),
+    (Command::Submit, "submit", "submit a form"),
+    (Command::Download, "download", "download a url"),
+    (
+        Command::PostDownload,
+        "post-download",
+        "send data and download the response",
+    ),
+    (
+        Command::Info,
+        "info",
+        "url, status, content type and headers",
+    ),
+    (Command::Cookies, "cookies", "list cookies and delete some"),
+    (Command::History, "history", "search every page visited"),
+    (
+        Command::Bookmarks,
+        "bookmarks",
+        "search the bookmarks and go to one",
+    ),
+    (Command::Bookmark, "bookmark", "bookmark this page"),
+    (
+        Command::ManageBookmarks,
+        "manage-bookmarks",
+        "delete, rename, move, tag, import, export",
+    ),
+    (Command::NewTab, "new-tab", "go to a url in a new tab"),
+    (Command::NextTab, "next-tab", "the tab to the right"),
+    (Command::PrevTab, "prev-tab", "the tab to the left"),
+    (Command::CloseTab, "close-tab", "close this tab"),
+    (Command::ListTabs, "list-tabs", "show the open tabs"),
+    (
+        Command::Execute,
+        "command-line",
+        "run a command, as :open url",
+    ),
+    (Command::Help, "help", "this list"),
+];
+
+const DEFAULTS: &[(&str, Command)] = &[
+    ("^c", Command::Quit),
+    ("esc", Command::Cancel),
+    ("^e", Command::ScrollDown),
+    ("down", Command::ScrollDown),
+    ("^y", Command::ScrollUp),
+    ("up", Command::ScrollUp),
+    ("^f", Command::PageDown),
+    ("pagedown", Command::PageDown),
+    ("^b", Command::PageUp),
+    ("pageup", Command::PageUp),
+    // no key is bound on its own and as the start of a sequence, that
+    // would wait to see if the sequence goes on
+    ("home", Command::Top),
+    ("<", Command::Top),
+    ("end", Command::Bottom),
+    (">", Command::Bottom),
+    ("^o", Command::Back),
+    ("H", Command::Back),
+    // most terminals send tab for ^i, those that tell them apart (with the
+    // kitty keyboard protocol) send ^i
+    ("^i", Command::Forward),
+    ("L", Command::Forward),
+    ("tab", Command::FocusNext),
+    ("j", Command::FocusNext),
+    ("backtab", Command::FocusPrev),
+    ("k", Command::FocusPrev),
+    ("enter", Command::OpenFocused),
+    ("g", Command::Goto),
+    ("G", Command::GotoCurrent),
+    ("a", Command::Follow),
+    ("A", Command::FollowNewTab),
+    ("f", Command::OpenFile),
+    ("d", Command::PostData),
+    ("e", Command::EditField),
+    ("s", Command::Submit),
+    ("w", Command::Download),
+    ("W", Command::PostDownload),
+    ("i", Command::Info),
+    ("c", Command::Cookies),
+    ("h", Command::History),
+    ("`", Command::Bookmarks),
+    ("m", Command::Bookmark),
+    ("M", Command::ManageBookmarks),
+    ("t", Command::NewTab),
+    ("]", Command::NextTab),
+    ("[", Command::PrevTab),
+    ("x", Command::CloseTab),
+    ("T", Command::ListTabs),
+    (":", Command::Execute),
+    ("/", Command::Search),
+    ("?", Command::SearchBack),
+    ("n", Command::SearchNext),
+    ("N", Command::SearchPrev),
+    ("f1", Command::Help),
+];
+
+impl Command {
+    pub fn from_name(name: &str) -> Option<Command> {
+        COMMANDS
+            .iter()
+            .find(|(_, n, _)| *n == name)
+            .map(|&(c, _, _)| c)
+    }
+}
+
+// keys and the command they run, none to unbind them
+pub type Binding = (Vec<Key>, Option<Command>);
+
+// what a sequence of keys pressed so far comes to
+pub enum Lookup {
+    // the command it is bound to, and nothing longer starts with it
+    Command(Command),
+    // a longer binding starts with it, the command is for when no more
+    // keys come
+    Prefix(Option<Command>),
+    None,
+}
+
+pub struct Keymap {
+    bindings: Vec<(Vec<Key>, Command)>,
+}
+
+impl Keymap {
+    // the default keymap with the bindings of the config on top. binding
+    // keys to no command takes them out.
+    pub fn new(overrides: &[Binding]) -> Keymap {
+        let mut bindings: Vec<(Vec<Key>, Command)> = DEFAULTS
+            .iter()
+            .map(|&(keys, command)| (parse_keys(keys).unwrap(), command))
+            .collect();
+        for (keys, command) in overrides {
+            bindings.retain(|(k, _)| k != keys);
+            if let Some(command) = command {
+                bindings.push((keys.clone(), *command));
+            }
+        }
+        Keymap { bindings }
+    }
+
+    pub fn lookup(&self, keys: &[Key]) -> Lookup {
+        let exact = self
+            .bindings
+            .iter()
+            .find(|(k, _)| k == keys)
+            .map(|&(_, c)| c);
+        let longer = self
+            .bindings
+            .iter()
+            .any(|(k, _)| k.len() > keys.len() && k.starts_with(keys));
+        match (exact, longer) {
+            (_, true) => Lookup::Prefix(exact),
+            (Some(command), false) => Lookup::Command(command),
+            (None, false) => Lookup::None,
+        }
+    }
+
+    // the commands key runs, pressed after the keys pending. what is left
+    // pending is the start of a sequence, to go on with the next key.
+    pub fn press(&self, pending: &mut Vec<Key>, key: Key) -> Vec<Command> {
+        let mut commands = Vec::new();
+        pending.push(key);
+        loop {
+            match self.lookup(pending) {
+                Lookup::Command(command) => {
+                    commands.push(command);
+                    pending.clear();
+                }
+                Lookup::Prefix(_) => (),
+                // no sequence goes on with key: the keys before run what
+                // they are bound to, and key starts afresh
+                Lookup::None if pending.len() > 1 => {
+                    pending.pop();
+                    if let Lookup::Prefix(Some(command)) = self.lookup(pending) {
+                        commands.push(command);
+                    }
+                    pending.clear();
+                    pending.push(key);
+                    continue;
+                }
+                Lookup::None => pending.clear(),
+            }
+            return commands;
+        }
+    }
+
+    // every command with the keys bound to it
+    pub fn help(&self) -> String {
+        let mut buf = String::from("keys\n\n");
+        for &(command, name, about) in COMMANDS {
+            let keys: Vec<String> = self
+                .bindings
+                .iter()
+                .filter(|(_, c)| *c == command)
+                .map(|(k, _)| keys_name(k))
+                .collect();
+            let keys = match keys.is_empty() {
+                true => "-".to_string(),
+                false => keys.join(" "),
+            };
+            writeln!(buf, "{:<14} {:<17} {}", keys, name, about).unwrap();
+        }
+        buf
+    }
+}
+
+const NAMES: &[(&str, KeyCode)] = &[
+    ("esc", KeyCode::Esc),
+    ("tab", KeyCode::Tab),
+    ("backtab", KeyCode::BackTab),
+    ("enter", KeyCode::Enter),
+    ("backspace", KeyCode::Backspace),
+    ("space", KeyCode::Char(' ')),
+    ("up", KeyCode::Up),
+    ("down", KeyCode::Down),
+    ("left", KeyCode::Left),
+    ("right", KeyCode::Right),
+    ("home", KeyCode::Home),
+    ("end", KeyCode::End),
+    ("pageup", KeyCode::PageUp),
+    ("pagedown", KeyCode::PageDown),
+    ("f1", KeyCode::F(1)),
+];
+
+pub fn parse_key(s: &str) -> Option<Key> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut name = s;
+    loop {
+        if let Some(rest) = name.strip_prefix('^').filter(|r| !r.is_empty()) {
+            modifiers |= KeyModifiers::CONTROL;
+            name = rest;
+        } else if let Some(rest) = name.strip_prefix("M-").filter(|r| !r.is_empty()) {
+            modifiers |= KeyModifiers::ALT;
+            name = rest;
+        } else {
+            break;
+        }
+    }
+    let lower = name.to_ascii_lowercase();
+    let code = match NAMES.iter().find(|(n, _)| *n == lower) {
+        Some(&(_, code)) => code,
+        None => {
+            let mut chars = name.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => KeyCode::Char(c),
+                _ => return None,
+            }
+        }
+    };
+    Some(normalize(modifiers, code))
+}
+
+// keys one after another, as "gg" or "^w j". with spaces between them,
+// each is a key of its own; without, a named key or every character is.
+pub fn parse_keys(s: &str) -> Option<Vec<Key>> {
+    let keys: Option<Vec<Key>> = match s.contains(' ') {
+        true => s.split_whitespace().map(parse_key).collect(),
+        false => match parse_key(s) {
+            Some(key) => Some(vec![key]),
+            None if !s.starts_with('^') && !s.starts_with("M-") => {
+                s.chars().map(|c| parse_key(&c.to_string())).collect()
+            }
+            None => None,
+        },
+    };
+    keys.filter(|keys| !keys.is_empty())
+}
+
+pub fn key_name(&(modifiers, code): &Key) -> String {
+    let name = match NAMES.iter().find(|(_, c)| *c == code) {
+        Some((name, _)) => name.to_string(),
+        None => match code {
+            KeyCode::Char(c) => c.to_string(),
+            _ => format!("{:?}", code).to_lowercase(),
+        },
+    };
+    let name = match modifiers.contains(KeyModifiers::CONTROL) {
+        true => format!("^{}", name),
+        false => name,
+    };
+    match modifiers.contains(KeyModifiers::ALT) {
+        true => format!("M-{}", name),
+        false => name,
+    }
+}
+
+fn keys_name(keys: &[Key]) -> String {
+    let names: Vec<String> = keys.iter().map(key_name).collect();
+    // named keys need spaces between them to be read back
+    match names.iter().all(|n| n.chars().count() == 1) {
+        true => names.concat(),
+        false => names.join(" "),
+    }
+}
+
+// terminals differ on whether shift comes with a character, so a key is
+// shifted when it is an upper case letter and only then. alt is kept.
+pub fn normalize(modifiers: KeyModifiers, code: KeyCode) -> Key {
+    let alt = modifiers & KeyModifiers::ALT;
+    let (modifiers, code) = match code {
+        KeyCode::Char(c) if modifiers.contains(KeyModifiers::CONTROL) => {
+            (KeyModifiers::CONTROL, KeyCode::Char(c.to_ascii_lowercase()))
+        }
+        KeyCode::Char(c) if c.is_uppercase() => (KeyModifiers::SHIFT, code),
+        KeyCode::Char(_) => (KeyModifiers::NONE, code),
+        // shift-tab comes as backtab, with shift or without
+        KeyCode::BackTab => (KeyModifiers::NONE, code),
+        _ => (modifiers, code),
+    };
+    (modifiers | alt, code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(s: &str) -> Key {
+        parse_key(s).unwrap()
+    }
+
+    fn keys(s: &str) -> Vec<Key> {
+        parse_keys(s).unwrap()
+    }
+
+    fn keymap(bindings: &[(&str, &str)]) -> Keymap {
+        let overrides: Vec<Binding> = bindings
+            .iter()
+            .map(|&(k, c)| (keys(k), Command::from_name(c)))
+            .collect();
+        Keymap::new(&overrides)
+    }
+
+    #[test]
+    fn parse() {
+        let (ctrl, alt, shift) = (
+            KeyModifiers::CONTROL,
+            KeyModifiers::ALT,
+            KeyModifiers::SHIFT,
+        );
+        assert_eq!(key("x"), (KeyModifiers::NONE, KeyCode::Char('x')));
+        assert_eq!(key("X"), (shift, KeyCode::Char('X')));
+        assert_eq!(key("^x"), (ctrl, KeyCode::Char('x')));
+        assert_eq!(key("^X"), (ctrl, KeyCode::Char('x')));
+        assert_eq!(key("M-x"), (alt, KeyCode::Char('x')));
+        assert_eq!(key("M-^x"), (alt | ctrl, KeyCode::Char('x')));
+        assert_eq!(key("^"), (KeyModifiers::NONE, KeyCode::Char('^')));
+        assert_eq!(key("Esc"), (KeyModifiers::NONE, KeyCode::Esc));
+        assert_eq!(key("^home"), (ctrl, KeyCode::Home));
+        assert_eq!(key("space"), (KeyModifiers::NONE, KeyCode::Char(' ')));
+        assert_ne!(key("^i"), key("tab"));
+        assert_eq!(parse_key("xy"), None);
+        assert_eq!(parse_key("^nope"), None);
+
+        assert_eq!(keys("gg"), [key("g"), key("g")]);
+        assert_eq!(keys("^w j"), [key("^w"), key("j")]);
+        assert_eq!(keys("home"), [key("home")]);
+        assert_eq!(keys("M-x"), [key("M-x")]);
+        assert_eq!(parse_keys(""), None);
+        assert_eq!(parse_keys("^wj"), None);
+        assert_eq!(parse_keys("M-xy"), None);
+
+        // names read back as they were given
+        for s in ["x", "X", "^x", "M-x", "M-^x", "esc", "^home", "f1"] {
+            assert_eq!(key_name(&key(s)), s);
+        }
+        assert_eq!(keys_name(&keys("gg")), "gg");
+        assert_eq!(keys_name(&keys("^w j")), "^w j");
+    }
+
+    #[test]
+    fn normalize_keys() {
+        let (ctrl, alt, shift) = (
+            KeyModifiers::CONTROL,
+            KeyModifiers::ALT,
+            KeyModifiers::SHIFT,
+        );
+        let none = KeyModifiers::NONE;
+        // with shift or without, an upper case letter is shifted
+        assert_eq!(normalize(shift, KeyCode::Char('G')), key("G"));
+        assert_eq!(normalize(none, KeyCode::Char('G')), key("G"));
+        assert_eq!(normalize(shift, KeyCode::Char('?')), key("?"));
+        assert_eq!(normalize(ctrl | shift, KeyCode::Char('W')), key("^w"));
+        assert_eq!(normalize(shift, KeyCode::BackTab), key("backtab"));
+        assert_eq!(normalize(none, KeyCode::Tab), key("tab"));
+        assert_eq!(normalize(ctrl, KeyCode::Char('i')), key("^i"));
+        // alt stays
+        assert_eq!(normalize(alt, KeyCode::Char('x')), key("M-x"));
+        assert_ne!(normalize(alt, KeyCode::Char('x')), key("x"));
+        assert_eq!(
+            normalize(alt | shift, KeyCode::Char('X')),
+            (alt | shift, KeyCode::Char('X'))
+        );
+        assert_eq!(normalize(alt | ctrl, KeyCode::Char('x')), key("M-^x"));
+        assert_eq!(normalize(alt, KeyCode::Enter), (alt, KeyCode::Enter));
+    }
+
+    #[test]
+    fn lookup() {
+        let map = keymap(&[("gh", "history"), ("^w j", "next-tab"), ("x", "none")]);
+        let command = |k: &str| match map.lookup(&keys(k)) {
+            Lookup::Command(c) => Some(c),
+            _ => None,
+        };
+        assert_eq!(command("G"), Some(Command::GotoCurrent));
+        assert_eq!(command("gh"), Some(Command::History));
+        assert_eq!(command("^w j"), Some(Command::NextTab));
+        assert_eq!(command("^i"), Some(Command::Forward));
+        assert_eq!(command("tab"), Some(Command::FocusNext));
+        assert!(matches!(
+            map.lookup(&keys("g")),
+            Lookup::Prefix(Some(Command::Goto))
+        ));
+        assert!(matches!(map.lookup(&keys("^w")), Lookup::Prefix(None)));
+        assert!(matches!(map.lookup(&keys("x")), Lookup::None));
+        assert!(matches!(map.lookup(&keys("gx")), Lookup::None));
+        assert!(matches!(map.lookup(&keys("M-a")), Lookup::None));
+    }
+
+    #[test]
+    fn no_default_waits_for_more_keys() {
+        let map = Keymap::new(&[]);
+        for (keys, _) in &map.bindings {
+            assert!(
+                matches!(map.lookup(keys), Lookup::Command(_)),
+                "{}",
+                keys_name(keys)
+            );
+        }
+    }
+
+    #[test]
+    fn press() {
+        let map = keymap(&[("gh", "history"), ("^w j", "next-tab")]);
+        let mut pending = Vec::new();
+        let mut press = |k: &str| map.press(&mut pending, key(k));
+        assert_eq!(press("a"), [Command::Follow]);
+        assert_eq!(press("g"), []);
+        assert_eq!(press("h"), [Command::History]);
+        // a key that goes on with no sequence runs as it would alone, after
+        // what the keys before are bound to
+        assert_eq!(press("g"), []);
+        assert_eq!(press("G"), [Command::Goto, Command::GotoCurrent]);
+        assert_eq!(press("g"), []);
+        assert_eq!(press("^w"), [Command::Goto]);
+        assert_eq!(press("^w"), []);
+        assert_eq!(press("j"), [Command::NextTab]);
+        assert_eq!(press("^w"), []);
+        assert_eq!(press("M-z"), []);
+        assert_eq!(press("g"), []);
+        assert_eq!(press("g"), [Command::Goto]);
+        assert_eq!(pending, [key("g")]);
+    }
+}
@@ -0,0 +1,244 @@
+// runs fetches on a worker thread, so the ui keeps going while a page loads.
+// the response comes back as events, one fetch at a time.
+
+use crate::fetch::{Fetcher, Request};
+use crate::http::Sink;
+use crate::url::Url;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub enum Event {
+    Head {
+        status: u16,
+        headers: Vec<(String, String)>,
+        url: Url,
+    },
+    Data(Vec<u8>),
+    Done(io::Result<()>),
+}
+
+struct Job {
+    id: u64,
+    req: Request,
+    cancel: Arc<AtomicBool>,
+}
+
+pub struct Progress<'a> {
+    pub url: &'a Url,
+    pub bytes: usize,
+    pub elapsed: Duration,
+}
+
+struct Running {
+    url: Url,
+    started: Instant,
+    bytes: usize,
+    cancel: Arc<AtomicBool>,
+}
+
+pub struct Loader {
+    jobs: Sender<Job>,
+    events: Receiver<(u64, Event)>,
+    event_tx: Sender<(u64, Event)>,
+    // what the next worker fetches with
+    spare: Box<dyn Fetcher>,
+    id: u64,
+    // the last fetch the worker is done with
+    done: u64,
+    running: Option<Running>,
+}
+
+impl Loader {
+    pub fn new(fetcher: Box<dyn Fetcher>) -> Loader {
+        let (event_tx, events) = mpsc::channel();
+        Loader {
+            jobs: worker(fetcher.fresh(), event_tx.clone()),
+            events,
+            event_tx,
+            spare: fetcher,
+            id: 0,
+            done: 0,
+            running: None,
+        }
+    }
+
+    // a new fetch replaces the running one
+    pub fn start(&mut self, req: Request) {
+        self.cancel();
+        // nothing is running, this only drops what is left of the old fetch
+        self.poll();
+        // a worker still stuck connecting or waiting on a stalled server is
+        // left to time out on its own, the new fetch gets a worker of its own
+        if self.done < self.id {
+            let spare = self.spare.fresh();
+            let fetcher = std::mem::replace(&mut self.spare, spare);
+            self.jobs = worker(fetcher, self.event_tx.clone());
+        }
+        self.id += 1;
+        let running = Running {
+            url: req.url.clone(),
+            started: Instant::now(),
+            bytes: 0,
+            cancel: Arc::new(AtomicBool::new(false)),
+        };
+        let job = Job {
+            id: self.id,
+            req,
+            cancel: running.cancel.clone(),
+        };
+        if self.jobs.send(job).is_ok() {
+            self.running = Some(running);
+        }
+    }
+
+    // the worker stops at the next piece of the body, whatever it sends
+    // until then is dropped. one that is slow getting there is left behind
+    // by the next start.
+    pub fn cancel(&mut self) {
+        if let Some(running) = self.running.take() {
+            running.cancel.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.running.is_some()
+    }
+
+    pub fn progress(&self) -> Option<Progress<'_>> {
+        self.running.as_ref().map(|running| Progress {
+            url: &running.url,
+            bytes: running.bytes,
+            elapsed: running.started.elapsed(),
+        })
+    }
+
+    // the next event of the running fetch, events of canceled ones are dropped
+    pub fn poll(&mut self) -> Option<Event> {
+        while let Ok((id, event)) = self.events.try_recv() {
+            if let Event::Done(_) = event {
+                self.done = self.done.max(id);
+            }
+            let Some(running) = self.running.as_mut().filter(|_| id == self.id) else {
+                continue;
+            };
+            match &event {
+                Event::Data(data) => running.bytes += data.len(),
+                Event::Done(_) => self.running = None,
+                Event::Head { .. } => (),
+            }
+            return Some(event);
+        }
+        None
+    }
+}
+
+// a thread fetching the jobs sent to it one at a time, until the sender is
+// dropped
+fn worker(mut fetcher: Box<dyn Fetcher>, events: Sender<(u64, Event)>) -> Sender<Job> {
+    let (jobs, job_rx) = mpsc::channel::<Job>();
+    thread::spawn(move || {
+        for job in job_rx {
+            let mut sink = EventSink {
+                id: job.id,
+                events: &events,
+                cancel: &job.cancel,
+            };
+            let res = fetcher.fetch(&job.req, &mut sink);
+            let _ = events.send((job.id, Event::Done(res)));
+        }
+    });
+    jobs
+}
+
+struct EventSink<'a> {
+    id: u64,
+    events: &'a Sender<(u64, Event)>,
+    cancel: &'a AtomicBool,
+}
+
+impl Sink for EventSink<'_> {
+    fn head(&mut self, status: u16, headers: &[(String, String)], url: &Url) {
+        let event = Event::Head {
+            status,
+            headers: headers.to_vec(),
+            url: url.clone(),
+        };
+        let _ = self.events.send((self.id, event));
+    }
+
+    fn data(&mut self, data: &[u8]) -> bool {
+        if self.cancel.load(Ordering::Relaxed) {
+            return false;
+        }
+        self.events
+            .send((self.id, Event::Data(data.to_vec())))
+            .is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    // hangs on http://stall/ until its sender goes, like a server that
+    // never answers, and serves anything else at once
+    struct Stalling {
+        stall: Arc<Mutex<Receiver<()>>>,
+    }
+
+    impl Fetcher for Stalling {
+        fn fetch(&mut self, req: &Request, sink: &mut dyn Sink) -> io::Result<()> {
+            if req.url.host() == Some("stall") {
+                let _ = self.stall.lock().unwrap().recv();
+            }
+            sink.head(200, &[], &req.url);
+            sink.data(req.url.to_string().as_bytes());
+            Ok(())
+        }
+
+        fn fresh(&self) -> Box<dyn Fetcher> {
+            Box::new(Stalling {
+                stall: self.stall.clone(),
+            })
+        }
+    }
+
+    fn finish(loader: &mut Loader) -> Vec<u8> {
+        let started = Instant::now();
+        let mut body = Vec::new();
+        while started.elapsed() < Duration::from_secs(5) {
+            match loader.poll() {
+                Some(Event::Data(data)) => body.extend(data),
+                Some(Event::Done(res)) => {
+                    res.unwrap();
+                    return body;
+                }
+                Some(Event::Head { .. }) => (),
+                None => thread::sleep(Duration::from_millis(5)),
+            }
+        }
+        panic!("the fetch never finished");
+    }
+
+    #[test]
+    fn a_stalled_fetch_does_not_hold_up_the_next() {
+        let (release, stall) = mpsc::channel();
+        let mut loader = Loader::new(Box::new(Stalling {
+            stall: Arc::new(Mutex::new(stall)),
+        }));
+        loader.start(Request::get(Url::parse("http://stall/").unwrap()));
+        thread::sleep(Duration::from_millis(20));
+        loader.cancel();
+        loader.start(Request::get(Url::parse("http://next/").unwrap()));
+        assert_eq!(finish(&mut loader), b"http://next/");
+        // the stalled one comes to nothing when it does finish
+        drop(release);
+        loader.start(Request::get(Url::parse("http://last/").unwrap()));
+        assert_eq!(finish(&mut loader), b"http://last/");
+    }
+}
@@ -1,260 +1,163 @@
-use crossterm::event::{poll, read, Event, KeyCode, KeyModifiers};
-use crossterm::terminal::{self, Clear, ClearType};
+use browser::Browser;
+use config::Config;
+use crossterm::event::{poll, read, DisableMouseCapture, EnableMouseCapture, Event};
+use crossterm::event::{
+    KeyboardEnhancementFlags, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::terminal;
 use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
 use crossterm::terminal::{EnterAlternateScreen, LeaveAlternateScreen};
 use crossterm::ExecutableCommand;
-use crossterm::{cursor, QueueableCommand};
-use history::*;
-use input::*;
-use page::*;
-use std::ffi::OsStr;
-use std::fs::File;
-use std::io::{self, Read};
-use std::process::{Command, Stdio};
-use std::time::Duration;
+use keys::{Key, Lookup};
+use loader::Progress;
+use std::time::{Duration, Instant};
 
+mod bookmarks;
+mod browser;
+mod charset;
+mod cmdline;
+mod config;
+mod cookie;
+mod dom;
+mod encode;
+mod entities;
+mod fetch;
+mod form;
+mod hints;
 mod history;
+mod http;
 mod input;
+mod keys;
+mod loader;
 mod page;
 mod pager;
+mod search;
+mod tab;
+mod tokenizer;
+mod url;
+mod visits;
 
-const USER_AGENT: &str = "ibrow/0.1.0";
+// how long the keys of a sequence, like gg, may be apart
+const SEQUENCE_TIMEOUT: Duration = Duration::from_millis(1000);
 
 fn main() -> std::io::Result<()> {
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(errors) => {
+            for e in errors {
+                eprintln!("ibrow: {}", e);
+            }
+            std::process::exit(1);
+        }
+    };
+    config::set_theme(config.theme);
+    let backend = std::env::var("IBROW_FETCHER")
+        .ok()
+        .filter(|b| !b.is_empty())
+        .unwrap_or(config.fetcher.clone());
     let mut stdout = std::io::stdout();
     stdout.execute(EnterAlternateScreen)?;
-    let mut screen_size: (u16, u16) = terminal::size().unwrap();
-
-    let mut cur_line = 0u16;
-    let mut history: History = History::new();
-
-    let mut bookmark_path = home::home_dir().unwrap();
-    bookmark_path.push(".ibrow.conf");
-    let bookmark_path = bookmark_path.into_os_string();
-    let mut bookmark = String::new();
-    if let Ok(mut f) = File::open(&bookmark_path) {
-        f.read_to_string(&mut bookmark).unwrap();
+    let mouse = config.mouse;
+    if mouse {
+        stdout.execute(EnableMouseCapture)?;
     }
+    let rows = terminal::size().unwrap().1;
 
-    if let Some(path) = std::env::args().nth(1) {
-        match File::open(path) {
-            Ok(mut file) => {
-                let mut buf = String::new();
-                file.read_to_string(&mut buf)?;
-                history.push(get_processed_page(&buf));
-            }
-            Err(_) => {
-                println!("could not find file");
-            }
-        }
-    }
+    let mut browser = Browser::new(config, backend, rows);
+    browser.restore(std::env::args().nth(1))?;
 
     enable_raw_mode()?;
-    loop {
-        if poll(Duration::from_millis(1000))? {
-            let ev = read()?;
-            let ev = match ev {
-                Event::Key(k) => k,
-                Event::Resize(w, h) => {
-                    screen_size.0 = w;
-                    screen_size.1 = h;
-                    continue;
-                }
-                _ => continue,
-            };
-            if ev.modifiers == KeyModifiers::CONTROL {
-                match ev.code {
-                    KeyCode::Char('c') => break,
-                    KeyCode::Char('e') => {
-                        cur_line = cur_line
-                            .saturating_add(1)
-                            .clamp(0, (history.current().line_count as u16) - 1);
-                        pager::pager(&history.current().buf, cur_line)?;
-                        // stdout.execute(terminal::ScrollUp(1))?;
-                    }
-                    KeyCode::Char('y') => {
-                        cur_line = cur_line.saturating_sub(1);
-                        pager::pager(&history.current().buf, cur_line)?;
-                        // stdout.execute(terminal::ScrollDown(1))?;
-                    }
-                    KeyCode::Char('f') => {
-                        cur_line = cur_line
-                            .saturating_add(screen_size.1 / 2)
-                            .clamp(0, history.current().line_count as u16 - 1);
-                        pager::pager(&history.current().buf, cur_line)?;
-                    }
-                    KeyCode::Char('b') => {
-                        cur_line = cur_line.saturating_sub(screen_size.1 / 2);
-                        pager::pager(&history.current().buf, cur_line)?;
-                    }
-                    KeyCode::Char('o') => {
-                        history.prev();
-                        pager::pager(&history.current().buf, 0).unwrap();
-                    }
-                    _ => (),
-                }
-            } else if ev.modifiers == KeyModifiers::NONE {
-                match ev.code {
-                    KeyCode::Tab => {
-                        history.next();
-                        pager::pager(&history.current().buf, 0).unwrap();
-                    }
-                    KeyCode::Char('f') => {
-                        let path = match get_input("files: ") {
-                            Ok(s) => s,
-                            Err(_) => continue,
-                        };
-                        match File::open(path) {
-                            Ok(mut file) => {
-                                let mut buf = String::new();
-                                file.read_to_string(&mut buf)?;
-                                history.push(get_processed_page(&buf));
-                            }
-                            Err(_) => {
-                                println!("could not find file");
-                            }
-                        }
-                        stdout.execute(cursor::MoveToColumn(0))?;
-                    }
-                    KeyCode::Char('g') => {
-                        let url = match get_input("goto: ") {
-                            Ok(s) => s,
-                            Err(_) => continue,
-                        };
-                        let buf = go_url(&url)?;
-                        history.push(get_processed_page(&buf));
-                    }
-                    KeyCode::Char('d') => {
-                        let Ok(data) = get_input("data: ") else {
-                            continue;
-                        };
-                        let buf = post(&history.current().url, &data)?;
-                        history.push(get_processed_page(&buf));
-                    }
-                    KeyCode::Char('`') => {
-                        let url = match get_input_with("goto: ", Some(&bookmark)) {
-                            Ok(s) => s,
-                            Err(_) => continue,
-                        };
-                        let buf = go_url(&url)?;
-                        history.push(get_processed_page(&buf));
-                    }
-                    KeyCode::Char('a') => {
-                        let Ok(s) = get_input("anchor index: ") else {
-                            continue;
-                        };
-                        let Ok(index) = s.parse::<usize>() else {
-                            continue;
-                        };
-                        let Some(url) = history.current().anchors.get(index) else {
-                            continue;
-                        };
-                        let url = if url.starts_with('/') {
-                            let cur_url = &history.current().url;
-                            let proto = cur_url.find("//").unwrap();
-                            let Some(base_end) = cur_url.get(proto + 2..).unwrap().find('/') else {
-                                continue;
-                            };
-                            let start = cur_url.find(' ').unwrap() + 1;
-                            let mut result = cur_url
-                                .get(start..proto + base_end + 2)
-                                .unwrap()
-                                .to_string();
-                            // println!("{}", cur_url);
-                            result.push_str(url);
-                            result
-                        } else {
-                            url.to_string()
-                        };
-                        if let Ok(buf) = go_url(&url) {
-                            history.push(get_processed_page(&buf));
-                        } else {
-                            println!("url: {}", url);
-                        }
-                    }
-                    KeyCode::Char('w') => {
-                        let Ok(s) = get_input_with("download: ", Some(&history.current().url))
-                        else {
-                            continue;
-                        };
-                        curl(["-LO", &s])?;
-                    }
-                    _ => (),
-                }
-                cur_line = 0;
-            } else {
-                match ev.code {
-                    KeyCode::Char('G') => {
-                        let url = match get_input_with("goto: ", Some(&history.current().url)) {
-                            Ok(s) => s,
-                            Err(_) => continue,
-                        };
-                        let buf = go_url(&url)?;
-                        history.push(get_processed_page(&buf));
-                    }
-                    KeyCode::Char('W') => {
-                        stdout.queue(cursor::MoveTo(0, 1))?;
-                        print!("{}", &history.current().url);
-                        let Ok(s) = get_input("data and download: ") else {
-                            continue;
-                        };
-                        curl(["-LO", &history.current().url, "-d", &s, "-A", USER_AGENT])?;
+    // terminals that can tell ^i from tab, ^m from enter and so on are asked
+    // to, so that those keys may be bound apart
+    let enhanced = terminal::supports_keyboard_enhancement().unwrap_or(false);
+    if enhanced {
+        stdout.execute(PushKeyboardEnhancementFlags(
+            KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES,
+        ))?;
+    }
+    // the keys of a sequence pressed so far, and when the last one was
+    let mut pending: Vec<Key> = Vec::new();
+    let mut pressed = Instant::now();
+    'keys: loop {
+        let loading = browser.poll()?;
+        if let Some(progress) = browser.tab().progress() {
+            pager::status(&progress_line(&progress))?;
+        }
+        let timeout = match (loading, pending.is_empty()) {
+            (_, false) => SEQUENCE_TIMEOUT.saturating_sub(pressed.elapsed()),
+            (true, true) => Duration::from_millis(50),
+            (false, true) => Duration::from_millis(1000),
+        };
+        if !poll(timeout)? {
+            // no more keys came, a sequence that is bound runs as it is
+            if !pending.is_empty() && pressed.elapsed() >= SEQUENCE_TIMEOUT {
+                let command = match browser.keymap.lookup(&pending) {
+                    Lookup::Prefix(command) => command,
+                    _ => None,
+                };
+                pending.clear();
+                pager::status("")?;
+                if let Some(command) = command {
+                    if !browser.run(command)? {
+                        break;
                     }
-                    _ => (),
                 }
             }
+            continue;
         }
+        let key = match read()? {
+            Event::Key(k) => keys::normalize(k.modifiers, k.code),
+            Event::Resize(_, h) => {
+                browser.rows = h;
+                continue;
+            }
+            Event::Mouse(m) => {
+                browser.mouse(m)?;
+                continue;
+            }
+            _ => continue,
+        };
+        if !pending.is_empty() {
+            pager::status("")?;
+        }
+        pressed = Instant::now();
+        for command in browser.keymap.press(&mut pending, key) {
+            if !browser.run(command)? {
+                break 'keys;
+            }
+        }
+        if !pending.is_empty() {
+            let typed: Vec<String> = pending.iter().map(keys::key_name).collect();
+            pager::status(&typed.join(" "))?;
+        }
+    }
+    if enhanced {
+        stdout.execute(PopKeyboardEnhancementFlags)?;
     }
     disable_raw_mode()?;
 
-    let mut bm = File::create(bookmark_path).expect("failed to create config file");
-    use std::io::Write;
-    write!(&mut bm, "{}", bookmark).expect("failed to write config file");
-
+    if mouse {
+        stdout.execute(DisableMouseCapture)?;
+    }
     stdout.execute(LeaveAlternateScreen)?;
+    browser.save()?;
     Ok(())
 }
 
-fn go_url(url: &str) -> io::Result<String> {
-    println!("{}", url);
-    curl(["-#", "-w %{url_effective}", "-L", url])
-}
-
-fn post(url: &str, data: &str) -> io::Result<String> {
-    println!("{}, {}", url, data);
-    curl([
-        "-#",
-        "-w %{url_effective}",
-        "-A",
-        "ibrow/0.1.0",
-        "-L",
-        url,
-        "-F",
-        data,
-    ])
-}
-
-fn curl<I, S>(args: I) -> io::Result<String>
-where
-    I: IntoIterator<Item = S>,
-    S: AsRef<OsStr>,
-{
-    let mut stdout = io::stdout();
-    stdout.execute(cursor::MoveTo(0, 0))?;
-    stdout.execute(Clear(ClearType::All))?;
-    disable_raw_mode()?;
-
-    let mut curl = Command::new("curl");
-    curl.args(args);
-    curl.stderr(Stdio::inherit());
-    let curl = curl.output().expect("curl error");
-    println!("{}", std::str::from_utf8(&curl.stderr).unwrap());
-    let curl_stdout = String::from_utf8(curl.stdout).expect("utf8 error");
-    if !curl.status.success() {
-        enable_raw_mode()?;
-        return Err(io::Error::new(io::ErrorKind::Other, ""));
-    }
-
-    enable_raw_mode()?;
-    Ok(curl_stdout)
+// spinner, url, bytes so far and time taken
+fn progress_line(progress: &Progress) -> String {
+    const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+    let frame = SPINNER[(progress.elapsed.as_millis() / 100) as usize % SPINNER.len()];
+    let bytes = match progress.bytes {
+        n if n < 1024 => format!("{} B", n),
+        n if n < 1024 * 1024 => format!("{:.1} KB", n as f64 / 1024.0),
+        n => format!("{:.1} MB", n as f64 / (1024.0 * 1024.0)),
+    };
+    format!(
+        "{} {}  {}  {:.1}s  (esc to cancel)",
+        frame,
+        progress.url,
+        bytes,
+        progress.elapsed.as_secs_f64()
+    )
 }
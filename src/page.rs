@@ -1,12 +1,74 @@
+use crate::charset::StreamDecoder;
+use crate::config;
+use crate::dom::{Dom, NodeData, TreeBuilder};
+use crate::encode;
+use crate::fetch::Request;
+use crate::form::{self, Field, Form};
 use crate::pager;
-use crossterm::style::Stylize;
+use crate::url::{ParseError, Url};
+use crossterm::style::{Color, Stylize};
+use encoding_rs::{Encoding, UTF_8};
 use std::fmt::Write;
+use std::fs;
+use std::io;
 
 pub struct Page {
     pub buf: String,
     pub line_count: usize,
-    pub url: String,
+    pub info: PageInfo,
     pub anchors: Vec<String>,
+    // where each of anchors is shown in buf
+    pub anchor_spans: Vec<Span>,
+    pub base: Option<String>,
+    pub title: Option<String>,
+    pub forms: Vec<Form>,
+    pub fields: Vec<Field>,
+    // the fields shown on the page, by the number they are shown with
+    pub controls: Vec<usize>,
+    // where each of controls is shown in buf
+    pub control_spans: Vec<Span>,
+    dom: Dom,
+}
+
+// a place in buf: the line, and the column on it counted in characters,
+// leaving out escape codes
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Spot {
+    pub line: usize,
+    pub column: usize,
+}
+
+// from start up to end, which is the spot after it
+#[derive(Clone, Copy)]
+pub struct Span {
+    pub start: Spot,
+    pub end: Spot,
+}
+
+// what can be focused on a page: an anchor or a control, by its number
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Anchor(usize),
+    Control(usize),
+}
+
+// where a page came from, and how it was decoded
+#[derive(Clone)]
+pub struct PageInfo {
+    pub url: Url,
+    pub status: Option<u16>,
+    pub headers: Vec<(String, String)>,
+    pub encoding: &'static str,
+    pub warning: Option<String>,
+}
+
+impl PageInfo {
+    pub fn content_type(&self) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case("content-type"))
+            .map(|(_, v)| v.as_str())
+    }
 }
 
 impl Page {
@@ -14,146 +76,483 @@ impl Page {
         Page {
             buf: String::new(),
             line_count: 0,
-            url: String::new(),
+            info: PageInfo {
+                url: Url::parse("about:blank").unwrap(),
+                status: None,
+                headers: Vec::new(),
+                encoding: "UTF-8",
+                warning: None,
+            },
             anchors: Vec::new(),
+            anchor_spans: Vec::new(),
+            base: None,
+            title: None,
+            forms: Vec::new(),
+            fields: Vec::new(),
+            controls: Vec::new(),
+            control_spans: Vec::new(),
+            dom: Dom::new(),
         }
     }
-}
 
-pub fn get_processed_page(page: &str) -> Page {
-    let curl_out = page.replace("&nbsp;", "\u{A0}");
-    let curl_out = curl_out.replace("&quot;", "\"");
-    let mut iter = curl_out.split(|c| c == '<');
+    // the field shown as {control: ...}
+    pub fn field(&self, control: usize) -> Option<&Field> {
+        self.controls.get(control).map(|&id| &self.fields[id])
+    }
 
-    let mut anchors: Vec<String> = Vec::new();
+    pub fn fill(&mut self, control: usize, input: &str) -> Result<(), String> {
+        let id = self.controls[control];
+        self.fields[id].fill(input)?;
+        self.rerender();
+        Ok(())
+    }
 
-    let mut input_group = false;
-    let mut buf = String::new();
-    loop {
-        if let Some(i) = iter.next() {
-            let cur_input_group = i.starts_with("input");
-            if input_group && !cur_input_group {
-                writeln!(&mut buf).unwrap();
-            }
-            input_group = cur_input_group;
-            write_elem(i, &mut buf, &mut anchors);
-        } else {
-            if input_group {
-                writeln!(&mut buf).unwrap();
+    pub fn toggle(&mut self, control: usize) {
+        form::toggle(&mut self.fields, self.controls[control]);
+        self.rerender();
+    }
+
+    // the request that sends a form, submitter is the button it was sent with
+    pub fn submit(&self, form: usize, submitter: Option<usize>) -> io::Result<Request> {
+        let form_data = &self.forms[form];
+        // it only closes the dialog it is in, nothing goes out
+        if form_data.method == "dialog" {
+            return Err(io::Error::other("a dialog form sends nothing"));
+        }
+        let mut url = match form_data.action.as_deref() {
+            Some(action) if !action.is_empty() => self.resolve(action)?,
+            _ => self.info.url.clone(),
+        };
+        let entries = form::entries(&self.fields, form, submitter);
+        let charset = Encoding::for_label(self.info.encoding.as_bytes()).unwrap_or(UTF_8);
+        if form_data.method == "get" {
+            // the fields replace whatever query the action had
+            url.set_query(Some(&encode::urlencoded(&entries, charset)));
+            return Ok(Request::get(url));
+        }
+        let (content_type, body) = encode::encode(&form_data.enctype, &entries, charset)?;
+        Ok(Request::post(url, &content_type, body))
+    }
+
+    // draws the page again with the fields as they are filled in now
+    fn rerender(&mut self) {
+        let dom = std::mem::replace(&mut self.dom, Dom::new());
+        let fields = std::mem::take(&mut self.fields);
+        *self = render(dom, self.info.clone(), fields);
+    }
+
+    // the <title>, or the url for a page without one
+    pub fn title(&self) -> String {
+        match &self.title {
+            Some(title) => title.clone(),
+            None => self.info.url.to_string(),
+        }
+    }
+
+    // the anchors and controls in the order they are shown
+    pub fn targets(&self) -> Vec<(Target, Span)> {
+        let anchors = self.anchor_spans.iter().enumerate();
+        let controls = self.control_spans.iter().enumerate();
+        let mut targets: Vec<(Target, Span)> = anchors
+            .map(|(i, &span)| (Target::Anchor(i), span))
+            .chain(controls.map(|(i, &span)| (Target::Control(i), span)))
+            .collect();
+        targets.sort_by_key(|(_, span)| span.start);
+        targets
+    }
+
+    // the anchor or control shown at spot
+    pub fn target_at(&self, spot: Spot) -> Option<Target> {
+        self.targets()
+            .into_iter()
+            .find(|(_, span)| span.start <= spot && spot < span.end)
+            .map(|(target, _)| target)
+    }
+
+    // the text of buf that span covers, without escape codes
+    pub fn text(&self, span: Span) -> String {
+        let lines = self.buf.lines().enumerate();
+        let lines = lines
+            .skip(span.start.line)
+            .take(span.end.line + 1 - span.start.line);
+        let mut text = Vec::new();
+        for (i, line) in lines {
+            let line: Vec<char> = pager::strip_escapes(line).chars().collect();
+            let from = if i == span.start.line {
+                span.start.column
+            } else {
+                0
+            };
+            let to = if i == span.end.line {
+                span.end.column
+            } else {
+                line.len()
+            };
+            let to = to.min(line.len());
+            text.push(line[from.min(to)..to].iter().collect::<String>());
+        }
+        text.join("\n")
+    }
+
+    pub fn span(&self, target: Target) -> Option<Span> {
+        match target {
+            Target::Anchor(i) => self.anchor_spans.get(i).copied(),
+            Target::Control(i) => self.control_spans.get(i).copied(),
+        }
+    }
+
+    // resolves a link against <base href> and the page url
+    pub fn resolve(&self, href: &str) -> Result<Url, ParseError> {
+        match &self.base {
+            Some(base) => self.info.url.join(base)?.join(href),
+            None => self.info.url.join(href),
+        }
+    }
+
+    pub fn info_buf(&self) -> String {
+        let info = &self.info;
+        let mut buf = String::new();
+        writeln!(buf, "{}", "page info".bold()).unwrap();
+        writeln!(buf, "url: {}", info.url).unwrap();
+        if let Some(title) = &self.title {
+            writeln!(buf, "title: {}", title).unwrap();
+        }
+        if let Some(base) = &self.base {
+            writeln!(buf, "base: {}", base).unwrap();
+        }
+        if let Some(status) = info.status {
+            writeln!(buf, "status: {}", status).unwrap();
+        }
+        let content_type = info.content_type().unwrap_or("unknown");
+        writeln!(buf, "content type: {}", content_type).unwrap();
+        writeln!(buf, "encoding: {}", info.encoding).unwrap();
+        if let Some(warning) = &info.warning {
+            writeln!(buf, "warning: {}", warning).unwrap();
+        }
+        writeln!(buf, "links: {}", self.anchors.len()).unwrap();
+        for (i, form) in self.forms.iter().enumerate() {
+            let action = form.action.as_deref().unwrap_or("");
+            let fields = self.fields.iter().filter(|f| f.form == Some(i)).count();
+            writeln!(
+                buf,
+                "form {}: {} {} ({}, {} fields)",
+                i,
+                form.method.to_uppercase(),
+                action,
+                form.enctype,
+                fields
+            )
+            .unwrap();
+        }
+        if !info.headers.is_empty() {
+            writeln!(buf).unwrap();
+            writeln!(buf, "{}", "headers".bold()).unwrap();
+            for (name, value) in &info.headers {
+                writeln!(buf, "{}: {}", name, value).unwrap();
             }
-            break;
         }
+        buf
     }
-    let buf = buf.replace("&lt;", "<");
-    let buf = buf.replace("&gt;", ">");
-    let line_count = buf.lines().count();
-    let idx = page.rfind('\n').unwrap();
-    let url = page.get(idx + 2..).unwrap_or_default().to_string();
-    println!("{}", url);
+}
 
-    pager::pager(&buf, 0).unwrap();
+// builds a page out of a body that arrives in pieces
+pub struct PageBuilder {
+    info: PageInfo,
+    decoder: StreamDecoder,
+    tree: TreeBuilder,
+}
+
+impl PageBuilder {
+    pub fn new(url: Url, status: Option<u16>, headers: Vec<(String, String)>) -> PageBuilder {
+        let info = PageInfo {
+            url,
+            status,
+            headers,
+            encoding: "UTF-8",
+            warning: None,
+        };
+        PageBuilder {
+            decoder: StreamDecoder::new(info.content_type()),
+            info,
+            tree: TreeBuilder::new(),
+        }
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) {
+        let text = self.decoder.feed(bytes);
+        self.tree.feed(&text);
+    }
+
+    // renders what has come in so far
+    pub fn page(&self) -> Page {
+        let mut info = self.info.clone();
+        info.encoding = self.decoder.encoding();
+        render(self.tree.dom().clone(), info, Vec::new())
+    }
+
+    pub fn finish(mut self) -> Page {
+        let text = self.decoder.finish();
+        self.tree.feed(&text);
+        self.info.encoding = self.decoder.encoding();
+        self.info.warning = self.decoder.warning();
+        render(self.tree.finish(), self.info, Vec::new())
+    }
+}
+
+pub fn page_from_file(path: &str) -> io::Result<Page> {
+    let bytes = fs::read(path)?;
+    let path = fs::canonicalize(path)?;
+    let url = Url::parse(&format!("file://{}", path.display()))?;
+    let mut builder = PageBuilder::new(url, None, Vec::new());
+    builder.feed(&bytes);
+    Ok(builder.finish())
+}
+
+// prev are the fields of an earlier render of the same dom, to keep what
+// was filled into them
+fn render(dom: Dom, info: PageInfo, prev: Vec<Field>) -> Page {
+    let mut renderer = Renderer::new(prev);
+    renderer.write_elem(&dom, Dom::ROOT);
+    renderer.end_input_group();
+    let Renderer {
+        buf,
+        anchors,
+        anchor_spans,
+        base,
+        title,
+        forms,
+        fields,
+        controls,
+        control_spans,
+        ..
+    } = renderer;
+
+    let line_count = buf.lines().count();
     Page {
         buf,
         line_count,
-        url,
+        info,
         anchors,
+        anchor_spans,
+        base,
+        title,
+        forms,
+        fields,
+        controls,
+        control_spans,
+        dom,
     }
 }
 
-fn write_elem(s: &str, buf: &mut String, anchors: &mut Vec<String>) {
-    if s.starts_with("br>") {
-        writeln!(buf).unwrap();
-    } else if s.starts_with("p>") {
-        writeln!(buf).unwrap();
-    } else if s.starts_with("/p>") {
-        print_rest(s, buf);
-        writeln!(buf).unwrap();
-    } else if s.starts_with("input") {
-        if let Some(name) = get_attr(s, "name") {
-            write!(buf, "__{}", name).unwrap();
-            if let Some(attr) = get_attr(s, "value") {
-                write!(buf, "={}", attr).unwrap();
+struct Renderer {
+    buf: String,
+    // the lines in buf up to counted
+    lines: usize,
+    counted: usize,
+    anchors: Vec<String>,
+    anchor_spans: Vec<Span>,
+    base: Option<String>,
+    title: Option<String>,
+    forms: Vec<Form>,
+    fields: Vec<Field>,
+    controls: Vec<usize>,
+    control_spans: Vec<Span>,
+    prev: Vec<Field>,
+    // the form being rendered
+    form: Option<usize>,
+    bold: bool,
+    italic: bool,
+    input_group: bool,
+}
+
+impl Renderer {
+    fn new(prev: Vec<Field>) -> Renderer {
+        Renderer {
+            buf: String::new(),
+            lines: 0,
+            counted: 0,
+            anchors: Vec::new(),
+            anchor_spans: Vec::new(),
+            base: None,
+            title: None,
+            forms: Vec::new(),
+            fields: Vec::new(),
+            controls: Vec::new(),
+            control_spans: Vec::new(),
+            prev,
+            form: None,
+            bold: false,
+            italic: false,
+            input_group: false,
+        }
+    }
+
+    fn add_field(&mut self, field: Field) {
+        let field = match self.prev.get(self.fields.len()) {
+            Some(prev) => prev.clone(),
+            None => field,
+        };
+        if field.is_visible() {
+            let shown = format!("{{{}: {}}}", self.controls.len(), field.display());
+            let start = self.spot();
+            self.write_colored(&shown, config::theme().field);
+            let end = self.spot();
+            self.controls.push(self.fields.len());
+            self.control_spans.push(Span { start, end });
+        }
+        self.fields.push(field);
+    }
+
+    fn end_input_group(&mut self) {
+        if self.input_group {
+            writeln!(self.buf).unwrap();
+        }
+        self.input_group = false;
+    }
+
+    fn write_children(&mut self, dom: &Dom, id: usize) {
+        for &child in &dom.nodes[id].children {
+            self.write_elem(dom, child);
+        }
+    }
+
+    fn write_elem(&mut self, dom: &Dom, id: usize) {
+        let e = match &dom.nodes[id].data {
+            NodeData::Document => return self.write_children(dom, id),
+            NodeData::Text(text) => return self.write_text(text),
+            NodeData::Element(e) => e,
+            NodeData::Doctype | NodeData::Comment => return,
+        };
+        if e.name == "input" {
+            let field = Field::input(e, self.form);
+            let visible = field.is_visible();
+            self.add_field(field);
+            if visible {
+                writeln!(self.buf).unwrap();
+                self.input_group = true;
+            }
+            return;
+        }
+        self.end_input_group();
+
+        match e.name.as_str() {
+            // a form inside a form is ignored, like the parser in the spec does
+            "form" if self.form.is_none() => {
+                self.forms.push(Form::new(e));
+                self.form = Some(self.forms.len() - 1);
+                self.write_children(dom, id);
+                self.form = None;
+            }
+            "button" => {
+                let field = Field::button(e, self.form, &dom.text(id));
+                self.add_field(field);
+            }
+            "textarea" => {
+                let field = Field::textarea(e, self.form, &dom.text(id));
+                self.add_field(field);
+            }
+            "select" => {
+                let mut options = Vec::new();
+                collect_options(dom, id, &mut options);
+                self.add_field(Field::select(e, self.form, options));
+            }
+            "br" => writeln!(self.buf).unwrap(),
+            "p" => {
+                writeln!(self.buf).unwrap();
+                self.write_children(dom, id);
+                writeln!(self.buf).unwrap();
+            }
+            "b" | "strong" => {
+                let bold = std::mem::replace(&mut self.bold, true);
+                self.write_children(dom, id);
+                self.bold = bold;
+            }
+            "i" | "em" => {
+                let italic = std::mem::replace(&mut self.italic, true);
+                self.write_children(dom, id);
+                self.italic = italic;
+            }
+            "script" | "style" | "option" | "template" => (),
+            "base" => {
+                if self.base.is_none() {
+                    self.base = e.attr("href").map(str::to_string);
+                }
             }
-            write!(buf, "__").unwrap();
-        }
-        print_rest(s, buf);
-        writeln!(buf).unwrap();
-    } else if s.starts_with("b>") {
-        if let Some(tag_end) = s.find('>') {
-            let text = s.get(tag_end + 1..).unwrap();
-            if !text.is_empty() {
-                write!(buf, "{}", text.bold()).unwrap();
+            "title" => {
+                if self.title.is_none() {
+                    let title = dom
+                        .text(id)
+                        .split_whitespace()
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    self.title = Some(title).filter(|t| !t.is_empty());
+                }
+                self.write_children(dom, id);
             }
-        } else {
-            write!(buf, "{}", s.bold()).unwrap();
-        }
-    } else if s.starts_with("i>") {
-        if let Some(tag_end) = s.find('>') {
-            let text = s.get(tag_end + 1..).unwrap();
-            if !text.is_empty() {
-                write!(buf, "{}", text.italic()).unwrap();
+            "tr" => {
+                writeln!(self.buf).unwrap();
+                self.write_children(dom, id);
             }
-        } else {
-            write!(buf, "{}", s.bold()).unwrap();
-        }
-    } else if s.starts_with("script") || s.starts_with("style") {
-    } else if s.starts_with("option") {
-    } else if s.starts_with("tr") {
-        writeln!(buf).unwrap();
-    } else if s.starts_with("a ") {
-        if let Some(a) = get_attr(s, "href") {
-            write!(buf, "[{}: ", anchors.len()).unwrap();
-            print_rest(s, buf);
-            write!(buf, "]").unwrap();
-            // write!(buf, "(").unwrap();
-            // write!(buf, "{}", a).unwrap();
-            // write!(buf, ")").unwrap();
-            anchors.push(a.to_owned());
-        }
-    } else if s.starts_with("span") || s.starts_with("h2") {
-        print_rest(s, buf);
-        writeln!(buf).unwrap();
-    } else {
-        print_rest(s, buf);
+            "a" => match e.attr("href") {
+                Some(href) => {
+                    let link = config::theme().link;
+                    let n = self.anchors.len();
+                    let start = self.spot();
+                    self.write_colored(&format!("[{}: ", n), link);
+                    self.anchors.push(href.to_string());
+                    self.anchor_spans.push(Span { start, end: start });
+                    self.write_children(dom, id);
+                    self.write_colored("]", link);
+                    self.anchor_spans[n].end = self.spot();
+                }
+                None => self.write_children(dom, id),
+            },
+            "span" | "h2" => {
+                self.write_children(dom, id);
+                writeln!(self.buf).unwrap();
+            }
+            _ => self.write_children(dom, id),
+        }
     }
-}
 
-fn print_rest(s: &str, buf: &mut String) {
-    if let Some(tag_end) = s.find('>') {
-        let tag_end = tag_end + 1;
-        let text = s
-            .get(tag_end..)
-            .unwrap()
-            .split_terminator(&['\n', '\r', '\t']);
-        for s in text {
-            write!(buf, "{}", s).unwrap();
+    // where what is written next goes
+    fn spot(&mut self) -> Spot {
+        self.lines += self.buf[self.counted..].matches('\n').count();
+        self.counted = self.buf.len();
+        let start = self.buf.rfind('\n').map_or(0, |i| i + 1);
+        Spot {
+            line: self.lines,
+            column: pager::strip_escapes(&self.buf[start..]).chars().count(),
         }
-    } else {
-        write!(buf, "{}", s).unwrap();
     }
-}
 
-fn is_quote(c: char) -> bool {
-    c == '"' || c == '\''
-}
+    fn write_colored(&mut self, s: &str, color: Option<Color>) {
+        match color {
+            Some(color) => write!(self.buf, "{}", s.with(color)).unwrap(),
+            None => self.buf.push_str(s),
+        }
+    }
 
-fn is_space(c: char) -> bool {
-    c == ' '
+    fn write_text(&mut self, text: &str) {
+        let text: String = text.split_terminator(&['\n', '\r', '\t']).collect();
+        if text.is_empty() {
+            return;
+        }
+        match (self.bold, self.italic) {
+            (true, _) => write!(self.buf, "{}", text.bold()).unwrap(),
+            (false, true) => write!(self.buf, "{}", text.italic()).unwrap(),
+            (false, false) => write!(self.buf, "{}", text).unwrap(),
+        }
+    }
 }
-type Pred = fn(c: char) -> bool;
-
-fn get_attr<'a>(input: &'a str, attr: &'a str) -> Option<&'a str> {
-    if let Some(idx) = input.find(attr) {
-        let s1 = input.get(idx..).unwrap();
-        let first_quote = s1.find(is_quote);
-        let (first_quote, next_query): (usize, Pred) = match first_quote {
-            Some(_) => (first_quote.unwrap() + 1, is_quote),
-            None => (0usize, is_space),
-        };
-        let s2 = s1.get(first_quote..).unwrap();
-        let second_quote = s2.find(next_query).unwrap();
-        Some(s2.get(..second_quote).unwrap())
-    } else {
-        None
+
+// the options of a select, including those in an optgroup
+fn collect_options(dom: &Dom, id: usize, options: &mut Vec<form::SelectOption>) {
+    for &child in &dom.nodes[id].children {
+        match dom.element(child) {
+            Some(e) if e.name == "option" => options.push(Field::option(e, &dom.text(child))),
+            Some(e) if e.name == "optgroup" => collect_options(dom, child, options),
+            _ => (),
+        }
     }
 }
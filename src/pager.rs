@@ -1,12 +1,28 @@
 use std::io;
 
+use crate::config;
+use crate::page::{Span, Spot};
+
 use crossterm::{
     cursor,
+    event::{read, Event},
+    style::{ContentStyle, Stylize},
     terminal::{self, disable_raw_mode, enable_raw_mode, Clear, ClearType},
     ExecutableCommand, QueueableCommand,
 };
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+// a row of the screen: the line of buf it shows, and the characters of
+// that line it starts and ends at, escape codes not counted
+pub struct Row {
+    pub line: usize,
+    pub start: usize,
+    pub end: usize,
+    text: String,
+}
 
-pub fn pager(buf: &String, line: u16) -> io::Result<()> {
+pub fn pager(buf: &str, line: u16) -> io::Result<()> {
     let mut stdout = io::stdout();
     stdout.queue(cursor::SavePosition)?;
     stdout.queue(cursor::MoveToColumn(0))?;
@@ -15,24 +31,17 @@ pub fn pager(buf: &String, line: u16) -> io::Result<()> {
     disable_raw_mode()?;
 
     let (screen_width, screen_height) = terminal::size()?;
-    let screen_width: usize = screen_width.into();
-    let screen_height: usize = screen_height.into();
-    let mut lines = buf.lines().skip(line.into());
-    let mut counter = 0usize;
-    while counter < screen_height {
-        if let Some(s) = lines.next() {
-            let wraps = s.len() / screen_width + 1;
-            if counter + wraps > screen_height {
-                break;
-            }
-            // print!("w: {}|", wraps);
-            print!("{}", s);
-            stdout.queue(cursor::MoveDown(1))?;
-            stdout.queue(cursor::MoveToColumn(0))?;
-            counter += wraps;
-        } else {
-            break;
+    let rows = layout(buf, line, screen_width.into(), screen_height.into());
+    let mut lines = buf.lines().enumerate().skip(line.into());
+    let mut current = lines.next();
+    // each row on its own, so the terminal never wraps a line itself
+    for (r, row) in rows.iter().enumerate() {
+        while current.is_some_and(|(i, _)| i < row.line) {
+            current = lines.next();
         }
+        let Some((_, s)) = current else { break };
+        stdout.queue(cursor::MoveTo(0, r as u16))?;
+        print!("{}", slice(s, row.start, row.end));
     }
 
     stdout.execute(cursor::RestorePosition)?;
@@ -40,3 +49,391 @@ pub fn pager(buf: &String, line: u16) -> io::Result<()> {
     enable_raw_mode()?;
     Ok(())
 }
+
+// the rows of a screen of width and height from line of buf on. only lines
+// that fit whole are shown, but for the first.
+pub fn layout(buf: &str, line: u16, width: usize, height: usize) -> Vec<Row> {
+    let mut rows = Vec::new();
+    for (i, s) in buf.lines().enumerate().skip(line.into()) {
+        let text: Vec<char> = strip_escapes(s).chars().collect();
+        let wraps = wrap(&text.iter().collect::<String>(), width);
+        if !rows.is_empty() && rows.len() + wraps.len() > height {
+            break;
+        }
+        for (start, end) in wraps.into_iter().take(height - rows.len()) {
+            let text = text[start..end].iter().collect();
+            rows.push(Row {
+                line: i,
+                start,
+                end,
+                text,
+            });
+        }
+        if rows.len() == height {
+            break;
+        }
+    }
+    rows
+}
+
+// where text breaks into rows of width, as the characters each row starts
+// and ends at. a row ends after the last space that fits, or anywhere in a
+// word wider than the screen. a space that doesn't fit is left out.
+fn wrap(text: &str, width: usize) -> Vec<(usize, usize)> {
+    let width = width.max(1);
+    let mut rows = Vec::new();
+    let (mut start, mut at, mut row_width) = (0, 0, 0);
+    // where the row could end after a space, and how wide it is up to there
+    let mut space: Option<(usize, usize)> = None;
+    for g in text.graphemes(true) {
+        let (w, chars) = (grapheme_width(g), g.chars().count());
+        let blank = g.trim().is_empty();
+        if row_width + w > width && at > start {
+            if blank {
+                rows.push((start, at));
+                at += chars;
+                (start, row_width, space) = (at, 0, None);
+                continue;
+            }
+            if let Some((after, before)) = space.take() {
+                rows.push((start, after));
+                (start, row_width) = (after, row_width - before);
+            }
+            // a word on its own that still doesn't fit breaks where it is
+            if row_width + w > width && at > start {
+                rows.push((start, at));
+                (start, row_width) = (at, 0);
+            }
+        }
+        row_width += w;
+        at += chars;
+        if blank {
+            space = Some((at, row_width));
+        }
+    }
+    rows.push((start, at));
+    rows
+}
+
+// how many columns g takes on screen. control characters are shown as
+// spaces.
+fn grapheme_width(g: &str) -> usize {
+    match g.chars().any(char::is_control) {
+        true => g.chars().count(),
+        false => g.width(),
+    }
+}
+
+fn text_width(s: &str) -> usize {
+    s.graphemes(true).map(grapheme_width).sum()
+}
+
+// the characters of s from start to end, escape codes not counted, with
+// the escape codes before them so they are styled as they are in s
+fn slice(s: &str, start: usize, end: usize) -> String {
+    let mut out = String::new();
+    let mut styled = false;
+    let mut at = 0;
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // csi sequences are kept, as strip_escapes reads them
+            if chars.next() == Some('[') {
+                out.push_str("\x1b[");
+                for c in chars.by_ref() {
+                    out.push(c);
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+                styled = true;
+            }
+            continue;
+        }
+        if at == end {
+            break;
+        }
+        if at >= start {
+            out.push(if c.is_control() { ' ' } else { c });
+        }
+        at += 1;
+    }
+    // the next row starts plain, the style is given again in front of it
+    if styled {
+        out.push_str("\x1b[0m");
+    }
+    out
+}
+
+// the row and column spot of buf is at on screen, if it is shown and not on
+// the status line. rows is the layout of the screen.
+pub fn place(rows: &[Row], height: usize, spot: Spot) -> Option<(u16, u16)> {
+    // the last row of the line that starts before spot, a spot in a space
+    // left out at the end of a row stays on that row
+    let (r, row) = rows
+        .iter()
+        .enumerate()
+        .rev()
+        .find(|(_, row)| row.line == spot.line && row.start <= spot.column)?;
+    let before: String = row.text.chars().take(spot.column - row.start).collect();
+    (r + 1 < height).then_some((r as u16, text_width(&before) as u16))
+}
+
+// the spot of buf shown at row and column, the other way from place. below
+// the last row is the end of it.
+pub fn spot_at(rows: &[Row], row: u16, column: u16) -> Option<Spot> {
+    let Some(row) = rows.get(usize::from(row)) else {
+        let last = rows.last()?;
+        return Some(Spot {
+            line: last.line,
+            column: last.end,
+        });
+    };
+    let mut at = row.start;
+    let mut x = 0;
+    for g in row.text.graphemes(true) {
+        x += grapheme_width(g);
+        if x > column.into() {
+            break;
+        }
+        at += g.chars().count();
+    }
+    Some(Spot {
+        line: row.line,
+        column: at,
+    })
+}
+
+// draws what each span covers in its style, over the page as pager drew it
+pub fn highlight(buf: &str, line: u16, marks: &[(Span, ContentStyle)]) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    let (width, height) = terminal::size()?;
+    let (width, height) = (usize::from(width), usize::from(height));
+    let rows = layout(buf, line, width, height);
+    stdout.queue(cursor::SavePosition)?;
+    for &(span, style) in marks {
+        // a piece for every row the span is on, but the status line
+        for (r, row) in rows.iter().enumerate().take(height.saturating_sub(1)) {
+            if row.line < span.start.line || row.line > span.end.line {
+                continue;
+            }
+            let from = match row.line == span.start.line {
+                true => span.start.column.max(row.start),
+                false => row.start,
+            };
+            let to = match row.line == span.end.line {
+                true => span.end.column.min(row.end),
+                false => row.end,
+            };
+            if from >= to {
+                continue;
+            }
+            let before: String = row.text.chars().take(from - row.start).collect();
+            let piece: String = row
+                .text
+                .chars()
+                .skip(from - row.start)
+                .take(to - from)
+                .collect();
+            stdout.queue(cursor::MoveTo(text_width(&before) as u16, r as u16))?;
+            print!("{}", style.apply(piece));
+        }
+    }
+    stdout.queue(cursor::RestorePosition)?;
+    io::Write::flush(&mut stdout)
+}
+
+// s without the escape codes that color it
+pub fn strip_escapes(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            out.push(c);
+            continue;
+        }
+        // a csi sequence ends with a letter, like the m of \x1b[1m
+        if chars.next() == Some('[') {
+            for c in chars.by_ref() {
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+        }
+    }
+    out
+}
+
+// puts text on the clipboard of the terminal, which may ignore it
+pub fn copy(text: &str) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    print!("\x1b]52;c;{}\x07", base64(text.as_bytes()));
+    io::Write::flush(&mut stdout)
+}
+
+// with padding, as the clipboard escape of terminals takes it
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => out.push(ALPHABET[(n >> (18 - 6 * i)) as usize & 63] as char),
+                false => out.push('='),
+            }
+        }
+    }
+    out
+}
+
+pub fn status(msg: &str) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    let (screen_width, screen_height) = terminal::size()?;
+    stdout.queue(cursor::SavePosition)?;
+    stdout.queue(cursor::MoveTo(0, screen_height.saturating_sub(1)))?;
+    stdout.queue(Clear(ClearType::CurrentLine))?;
+    // a wrapped status line would scroll the whole screen
+    let msg: String = msg.chars().take(screen_width.into()).collect();
+    match config::theme().status {
+        Some(color) => print!("{}", msg.with(color)),
+        None => print!("{}", msg),
+    }
+    stdout.queue(cursor::RestorePosition)?;
+    io::Write::flush(&mut stdout)?;
+    Ok(())
+}
+
+// shows buf until a key is pressed
+pub fn show(buf: &str) -> io::Result<()> {
+    pager(buf, 0)?;
+    loop {
+        if let Event::Key(_) = read()? {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the rows of layout, as the line, start, end and text of each
+    fn rows(
+        buf: &str,
+        line: u16,
+        width: usize,
+        height: usize,
+    ) -> Vec<(usize, usize, usize, String)> {
+        layout(buf, line, width, height)
+            .into_iter()
+            .map(|r| (r.line, r.start, r.end, r.text))
+            .collect()
+    }
+
+    fn spot(line: usize, column: usize) -> Spot {
+        Spot { line, column }
+    }
+
+    #[test]
+    fn wrap_at_spaces() {
+        assert_eq!(wrap("hello world", 8), [(0, 6), (6, 11)]);
+        assert_eq!(wrap("hello world", 11), [(0, 11)]);
+        // the space a row would end with is left out
+        assert_eq!(wrap("abcd efgh", 4), [(0, 4), (5, 9)]);
+        assert_eq!(wrap("a  b", 1), [(0, 1), (2, 3), (3, 4)]);
+        // a word wider than the screen breaks where it has to
+        assert_eq!(wrap("abcdefghij", 4), [(0, 4), (4, 8), (8, 10)]);
+        assert_eq!(wrap("ab cdefghij", 4), [(0, 3), (3, 7), (7, 11)]);
+        assert_eq!(wrap("", 4), [(0, 0)]);
+        assert_eq!(wrap("ab", 0), [(0, 1), (1, 2)]);
+    }
+
+    #[test]
+    fn wrap_by_width_on_screen() {
+        // wide characters take two columns, and a grapheme is never split
+        assert_eq!(wrap("日本語", 4), [(0, 2), (2, 3)]);
+        assert_eq!(wrap("日本語", 5), [(0, 2), (2, 3)]);
+        assert_eq!(wrap("a👍🏽b", 3), [(0, 3), (3, 4)]);
+        assert_eq!(wrap("e\u{301}e\u{301}e\u{301}", 2), [(0, 4), (4, 6)]);
+        // control characters are shown as spaces
+        assert_eq!(wrap("a\tb", 2), [(0, 2), (2, 3)]);
+        assert_eq!(text_width("日本 a👍🏽\u{7}"), 9);
+    }
+
+    #[test]
+    fn layout_shows_whole_lines() {
+        let buf = "one\ntwo three four\nfive";
+        let s = str::to_string;
+        assert_eq!(
+            rows(buf, 0, 9, 3),
+            [
+                (0, 0, 3, s("one")),
+                (1, 0, 9, s("two three")),
+                (1, 10, 14, s("four")),
+            ]
+        );
+        // a line that doesn't fit isn't begun
+        assert_eq!(rows(buf, 0, 9, 2), [(0, 0, 3, s("one"))]);
+        // but for the first
+        assert_eq!(rows(buf, 1, 9, 1), [(1, 0, 9, s("two three"))]);
+        assert_eq!(rows(buf, 2, 9, 3), [(2, 0, 4, s("five"))]);
+        assert!(rows(buf, 3, 9, 3).is_empty());
+        // escape codes take no room
+        assert_eq!(
+            rows("\x1b[1mbold\x1b[0m text", 0, 4, 3),
+            [(0, 0, 4, s("bold")), (0, 5, 9, s("text"))]
+        );
+    }
+
+    #[test]
+    fn slices_keep_their_style() {
+        let s = "\x1b[1mbold\x1b[0m text";
+        assert_eq!(slice(s, 0, 4), "\x1b[1mbold\x1b[0m\x1b[0m");
+        assert_eq!(slice(s, 5, 9), "\x1b[1m\x1b[0mtext\x1b[0m");
+        assert_eq!(slice("日本語", 1, 3), "本語");
+        assert_eq!(slice("a\tb", 0, 3), "a b");
+        assert_eq!(strip_escapes(s), "bold text");
+        assert_eq!(strip_escapes("\x1b[38;5;12mblue\x1b[39m"), "blue");
+    }
+
+    #[test]
+    fn place_and_spot_at_go_both_ways() {
+        let rows = layout("hello world\n日本語", 0, 8, 4);
+        assert_eq!(place(&rows, 4, spot(0, 3)), Some((0, 3)));
+        assert_eq!(place(&rows, 4, spot(0, 6)), Some((1, 0)));
+        assert_eq!(place(&rows, 4, spot(0, 8)), Some((1, 2)));
+        assert_eq!(place(&rows, 4, spot(1, 2)), Some((2, 4)));
+        // not on the status line, nor off the screen
+        assert_eq!(place(&rows, 2, spot(0, 8)), None);
+        assert_eq!(place(&rows, 4, spot(2, 0)), None);
+
+        let at = |row, column| spot_at(&rows, row, column).map(|s| (s.line, s.column));
+        assert_eq!(at(0, 3), Some((0, 3)));
+        assert_eq!(at(1, 2), Some((0, 8)));
+        // past the end of a row is its end
+        assert_eq!(at(1, 7), Some((0, 11)));
+        // either column of a wide character is that character
+        assert_eq!(at(2, 2), Some((1, 1)));
+        assert_eq!(at(2, 3), Some((1, 1)));
+        // below the last row is the end
+        assert_eq!(at(3, 0), Some((1, 3)));
+        for (line, column) in [(0, 0), (0, 5), (0, 7), (0, 10), (1, 0), (1, 2)] {
+            let (r, c) = place(&rows, 4, spot(line, column)).unwrap();
+            assert_eq!(at(r, c), Some((line, column)));
+        }
+    }
+
+    #[test]
+    fn base64_pads() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+        assert_eq!(base64(&[0xff, 0xfe]), "//4=");
+    }
+}
@@ -0,0 +1,272 @@
+// searching the text of a page. / searches down from the top of the screen
+// and ? up from the bottom, going to the nearest match as the query is
+// typed.
+
+use crate::keys;
+use crate::page::{Page, Span, Spot};
+use crate::pager;
+use crate::tab::Tab;
+use crossterm::event::{read, Event, KeyCode, KeyModifiers};
+use crossterm::terminal;
+use regex::RegexBuilder;
+use std::io;
+
+#[derive(Clone, Default)]
+pub struct Query {
+    pub text: String,
+    pub backward: bool,
+    // the text is a regular expression, not plain text
+    pub regex: bool,
+    pub match_case: bool,
+}
+
+impl Query {
+    // every match on page, in order. a match doesn't go over the end of a
+    // line.
+    pub fn find(&self, page: &Page) -> Result<Vec<Span>, String> {
+        let pattern = match self.regex {
+            true => self.text.clone(),
+            false => regex::escape(&self.text),
+        };
+        let re = RegexBuilder::new(&pattern)
+            .case_insensitive(!self.match_case)
+            .build()
+            // the last line of the error says what is wrong
+            .map_err(|e| {
+                e.to_string()
+                    .lines()
+                    .last()
+                    .unwrap_or("")
+                    .trim()
+                    .to_string()
+            })?;
+        let mut found = Vec::new();
+        for (line, s) in page.buf.lines().enumerate() {
+            let s = pager::strip_escapes(s);
+            // columns count characters, the matches are in bytes
+            let (mut byte, mut column) = (0, 0);
+            let mut column_of = |at: usize| {
+                column += s[byte..at].chars().count();
+                byte = at;
+                column
+            };
+            for m in re.find_iter(&s).filter(|m| !m.is_empty()) {
+                let start = Spot {
+                    line,
+                    column: column_of(m.start()),
+                };
+                let end = Spot {
+                    line,
+                    column: column_of(m.end()),
+                };
+                found.push(Span { start, end });
+            }
+        }
+        Ok(found)
+    }
+
+    // what the status line says about the search on tab
+    pub fn report(&self, tab: &Tab) -> String {
+        let mut modes = String::new();
+        if self.regex {
+            modes.push_str(" [regex]");
+        }
+        if self.match_case {
+            modes.push_str(" [case]");
+        }
+        let found = match (tab.found_at, tab.found.len()) {
+            (_, 0) => "no match".to_string(),
+            (Some(i), n) => format!("match {} of {}", i + 1, n),
+            (None, n) => format!("{} matches", n),
+        };
+        format!("{}{}  {}{}", self.prompt(), self.text, found, modes)
+    }
+
+    fn prompt(&self) -> &'static str {
+        match self.backward {
+            true => "?",
+            false => "/",
+        }
+    }
+}
+
+// the match to go to first, from the top of the screen down or from the
+// bottom up, going around at the end
+pub fn first(tab: &Tab, backward: bool) -> io::Result<Option<usize>> {
+    if tab.found.is_empty() {
+        return Ok(None);
+    }
+    let top = Spot {
+        line: tab.line.into(),
+        column: 0,
+    };
+    if !backward {
+        let below = tab.found.iter().position(|span| span.start >= top);
+        return Ok(Some(below.unwrap_or(0)));
+    }
+    let (width, height) = terminal::size()?;
+    let shown = pager::layout(
+        &tab.shown().buf,
+        tab.line,
+        width.max(1).into(),
+        height.into(),
+    );
+    let bottom = Spot {
+        line: shown.last().map_or(top.line, |row| row.line + 1),
+        column: 0,
+    };
+    let above = tab.found.iter().rposition(|span| span.start < bottom);
+    Ok(Some(above.unwrap_or(tab.found.len() - 1)))
+}
+
+// the match after the one gone to, or before it, going around at the ends
+pub fn next(tab: &Tab, backward: bool) -> io::Result<Option<usize>> {
+    let n = tab.found.len();
+    match tab.found_at {
+        Some(i) if backward => Ok(Some((i + n - 1) % n)),
+        Some(i) => Ok(Some((i + 1) % n)),
+        None => first(tab, backward),
+    }
+}
+
+// reads a query on the status line, going to the first match of what is
+// typed so far. ^r toggles regular expressions and ^e matching case. esc
+// puts the tab back where it was.
+pub fn prompt(tab: &mut Tab, mut query: Query) -> io::Result<Option<Query>> {
+    let line = tab.line;
+    loop {
+        tab.line = line;
+        tab.found_at = None;
+        let error = match query.text.is_empty() {
+            true => {
+                tab.found.clear();
+                None
+            }
+            false => match query.find(tab.shown()) {
+                Ok(found) => {
+                    tab.found = found;
+                    None
+                }
+                Err(e) => {
+                    tab.found.clear();
+                    Some(e)
+                }
+            },
+        };
+        match first(tab, query.backward)? {
+            Some(i) => tab.go_to_found(i)?,
+            None => tab.draw()?,
+        }
+        let status = match &error {
+            Some(e) => format!("{}{}  {}", query.prompt(), query.text, e),
+            None if query.text.is_empty() => {
+                format!("{}  (^r regex, ^e match case)", query.prompt())
+            }
+            None => query.report(tab),
+        };
+        pager::status(&status)?;
+
+        let Event::Key(k) = read()? else { continue };
+        match keys::normalize(k.modifiers, k.code) {
+            (_, KeyCode::Esc) | (KeyModifiers::CONTROL, KeyCode::Char('c')) => {
+                tab.line = line;
+                tab.found.clear();
+                tab.found_at = None;
+                tab.draw()?;
+                return Ok(None);
+            }
+            // a query that is no good stays to be fixed
+            (_, KeyCode::Enter) | (KeyModifiers::CONTROL, KeyCode::Char('j' | 'm'))
+                if error.is_none() =>
+            {
+                return Ok(Some(query));
+            }
+            (KeyModifiers::CONTROL, KeyCode::Char('r')) => query.regex = !query.regex,
+            (KeyModifiers::CONTROL, KeyCode::Char('e')) => query.match_case = !query.match_case,
+            (KeyModifiers::CONTROL, KeyCode::Char('u')) => query.text.clear(),
+            (_, KeyCode::Backspace) | (KeyModifiers::CONTROL, KeyCode::Char('h')) => {
+                query.text.pop();
+            }
+            (KeyModifiers::NONE | KeyModifiers::SHIFT, KeyCode::Char(c)) => query.text.push(c),
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(buf: &str) -> Page {
+        let mut page = Page::new();
+        page.buf = buf.to_string();
+        page
+    }
+
+    fn query(text: &str) -> Query {
+        Query {
+            text: text.to_string(),
+            ..Query::default()
+        }
+    }
+
+    // where each match is, as line, start and end column
+    fn found(query: &Query, buf: &str) -> Vec<(usize, usize, usize)> {
+        query
+            .find(&page(buf))
+            .unwrap()
+            .iter()
+            .map(|s| {
+                assert_eq!(s.start.line, s.end.line);
+                (s.start.line, s.start.column, s.end.column)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn matches_in_order() {
+        let buf = "The cat\nno match here\ncat, Cat and CAT";
+        assert_eq!(
+            found(&query("cat"), buf),
+            [(0, 4, 7), (2, 0, 3), (2, 5, 8), (2, 13, 16)]
+        );
+        assert!(found(&query("dog"), buf).is_empty());
+        // the text is no pattern
+        assert_eq!(found(&query("a.d"), "a.d and"), [(0, 0, 3)]);
+    }
+
+    #[test]
+    fn case_only_when_asked() {
+        let mut q = query("Cat");
+        assert_eq!(found(&q, "cat Cat").len(), 2);
+        q.match_case = true;
+        assert_eq!(found(&q, "cat Cat"), [(0, 4, 7)]);
+    }
+
+    #[test]
+    fn regular_expressions() {
+        let mut q = query(r"\d+");
+        assert!(found(&q, "a 12 b 345").is_empty());
+        q.regex = true;
+        assert_eq!(found(&q, "a 12 b 345"), [(0, 2, 4), (0, 7, 10)]);
+        // a match doesn't go over the end of a line, and empty ones are
+        // left out
+        q.text = "a.*".to_string();
+        assert_eq!(found(&q, "xa1\na2"), [(0, 1, 3), (1, 0, 2)]);
+        q.text = "z*".to_string();
+        assert!(found(&q, "abc").is_empty());
+        q.text = "(".to_string();
+        let error = q.find(&page("abc")).err().unwrap();
+        assert_eq!(error, "error: unclosed group");
+    }
+
+    #[test]
+    fn columns_count_characters_not_escapes() {
+        let buf = "\x1b[1mbold\x1b[0m café — naïve ünïcode\n日本語のテキスト";
+        assert_eq!(found(&query("naïve"), buf), [(0, 12, 17)]);
+        assert_eq!(found(&query("テキ"), buf), [(1, 4, 6)]);
+        assert_eq!(found(&query("bold"), buf), [(0, 0, 4)]);
+        assert!(found(&query("1mbold"), buf).is_empty());
+        assert_eq!(found(&query("ÜNÏ"), buf), [(0, 18, 21)]);
+    }
+}
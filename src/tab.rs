@@ -0,0 +1,586 @@
+// a tab has its own history, scroll position and fetch. tabs behind the
+// one on screen keep loading, but only the one on screen draws.
+
+use crate::config;
+use crate::fetch::{Fetcher, Request};
+use crate::history::History;
+use crate::loader::{Event, Loader, Progress};
+use crate::page::{page_from_file, Page, PageBuilder, Span, Target};
+use crate::pager;
+use crate::url::{self, Url};
+use crate::visits::Visits;
+use crossterm::style::{ContentStyle, Stylize};
+use crossterm::terminal;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+// while a page streams in, it is redrawn at most this often once it fills
+// the screen
+const REDRAW_INTERVAL: Duration = Duration::from_millis(250);
+
+// where the response of the running fetch goes
+enum Load {
+    Page {
+        // made once the response head is in
+        builder: Option<Box<PageBuilder>>,
+        // what is on screen of it so far
+        shown: Option<Box<Page>>,
+        drawn: Instant,
+        // how much of the body has come in, and had when it was drawn
+        bytes: usize,
+        drawn_bytes: usize,
+    },
+    Download {
+        name: String,
+        file: Option<File>,
+        bytes: usize,
+    },
+}
+
+pub struct Tab {
+    pub history: History,
+    // how far the page on screen is scrolled
+    pub line: u16,
+    // whether this is the tab on screen
+    pub visible: bool,
+    // the link or field picked out on the page on screen
+    pub focus: Option<Target>,
+    // the matches of a search on it, and the one gone to
+    pub found: Vec<Span>,
+    pub found_at: Option<usize>,
+    loader: Loader,
+    loading: Option<Load>,
+}
+
+impl Tab {
+    pub fn new(fetcher: Box<dyn Fetcher>) -> Tab {
+        Tab {
+            history: History::new(),
+            line: 0,
+            visible: false,
+            focus: None,
+            found: Vec::new(),
+            found_at: None,
+            loader: Loader::new(fetcher),
+            loading: None,
+        }
+    }
+
+    // the page on screen: the loading one once some of it is drawn
+    pub fn shown(&self) -> &Page {
+        match &self.loading {
+            Some(Load::Page {
+                shown: Some(page), ..
+            }) => page,
+            _ => self.history.current(),
+        }
+    }
+
+    // fetches from now on go through fetcher, the running one is dropped
+    pub fn set_fetcher(&mut self, fetcher: Box<dyn Fetcher>) {
+        self.stop();
+        self.loader = Loader::new(fetcher);
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.loader.is_loading()
+    }
+
+    pub fn progress(&self) -> Option<Progress<'_>> {
+        self.loader.progress()
+    }
+
+    fn status(&self, msg: &str) -> io::Result<()> {
+        match self.visible {
+            true => pager::status(msg),
+            false => Ok(()),
+        }
+    }
+
+    pub fn draw(&self) -> io::Result<()> {
+        if !self.visible {
+            return Ok(());
+        }
+        let page = self.shown();
+        pager::pager(&page.buf, self.line)?;
+        let mut marks: Vec<(Span, ContentStyle)> = self
+            .found
+            .iter()
+            .enumerate()
+            .map(|(i, &span)| match Some(i) == self.found_at {
+                true => (span, ContentStyle::new().reverse().bold().underlined()),
+                false => (span, ContentStyle::new().reverse()),
+            })
+            .collect();
+        if let Some(span) = self.focus.and_then(|target| page.span(target)) {
+            marks.push((span, ContentStyle::new().reverse()));
+        }
+        match marks.is_empty() {
+            true => Ok(()),
+            false => pager::highlight(&page.buf, self.line, &marks),
+        }
+    }
+
+    // drops what was picked out and found on the page that was on screen
+    fn forget_page(&mut self) {
+        self.focus = None;
+        self.found.clear();
+        self.found_at = None;
+    }
+
+    // scrolls down by lines, up when it is negative, as far as the last line
+    pub fn scroll(&mut self, by: i32) -> io::Result<()> {
+        let last = self.shown().line_count.saturating_sub(1);
+        let line =
+            (i64::from(self.line) + i64::from(by)).clamp(0, last.min(u16::MAX.into()) as i64);
+        self.line = line as u16;
+        self.draw()
+    }
+
+    // goes to the i-th match found, scrolling to it when it is off screen
+    pub fn go_to_found(&mut self, i: usize) -> io::Result<()> {
+        self.found_at = Some(i);
+        let spot = self.found[i].start;
+        let (width, height) = terminal::size()?;
+        let (width, height) = (usize::from(width).max(1), usize::from(height));
+        let shown = pager::layout(&self.shown().buf, self.line, width, height);
+        if pager::place(&shown, height, spot).is_none() {
+            // a little way down the screen, so what comes before shows too
+            let line = spot.line.saturating_sub(height / 4);
+            self.line = line.min(u16::MAX.into()) as u16;
+        }
+        self.draw()
+    }
+
+    // moves the focus to the next link or field on screen, or the one
+    // before, going around at the ends
+    pub fn move_focus(&mut self, forward: bool) -> io::Result<()> {
+        let page = self.shown();
+        let (width, height) = terminal::size()?;
+        let (width, height) = (usize::from(width).max(1), usize::from(height));
+        let shown = pager::layout(&page.buf, self.line, width, height);
+        let mut targets: Vec<Target> = page
+            .targets()
+            .into_iter()
+            .filter(|(_, span)| pager::place(&shown, height, span.start).is_some())
+            .map(|(target, _)| target)
+            .collect();
+        if !forward {
+            targets.reverse();
+        }
+        let next = match targets.iter().position(|&t| Some(t) == self.focus) {
+            Some(i) => targets.get(i + 1).or(targets.first()),
+            None => targets.first(),
+        };
+        match next {
+            Some(&target) => {
+                self.focus = Some(target);
+                self.draw()
+            }
+            None => self.status("no links on screen"),
+        }
+    }
+
+    fn open(&mut self, page: io::Result<Page>, visits: &mut Visits) -> io::Result<()> {
+        let page = match page {
+            Ok(page) => page,
+            Err(e) => return self.status(&e.to_string()),
+        };
+        if let Err(e) = visits.add(&page.info.url, &page.title.clone().unwrap_or_default()) {
+            self.status(&format!("can't save history: {}", e))?;
+        }
+        let warning = page.info.warning.clone();
+        self.history.push(page);
+        self.forget_page();
+        self.draw()?;
+        if let Some(warning) = warning {
+            self.status(&warning)?;
+        }
+        Ok(())
+    }
+
+    // keeps where the current page is scrolled to in its history entry,
+    // unless it is a loading page that is on screen
+    fn keep_line(&mut self) {
+        if !matches!(self.loading, Some(Load::Page { shown: Some(_), .. })) {
+            self.history.set_line(self.line);
+        }
+    }
+
+    pub fn open_file(&mut self, path: &str, visits: &mut Visits) -> io::Result<()> {
+        self.keep_line();
+        self.line = 0;
+        self.open(page_from_file(path), visits)
+    }
+
+    // back and forward in the history, to where each page was left
+    pub fn back(&mut self) -> io::Result<()> {
+        self.keep_line();
+        self.stop();
+        self.history.prev();
+        self.line = self.history.line();
+        self.forget_page();
+        self.draw()
+    }
+
+    pub fn forward(&mut self) -> io::Result<()> {
+        self.keep_line();
+        self.stop();
+        self.history.next();
+        self.line = self.history.line();
+        self.forget_page();
+        self.draw()
+    }
+
+    pub fn stop(&mut self) {
+        self.loader.cancel();
+        if let Some(Load::Download { name, file, .. }) = self.loading.take() {
+            discard(&name, file);
+        }
+    }
+
+    // stops a loading page, keeping what is on screen of it as the current
+    // page, so the fields picked from the screen are the ones acted on
+    pub fn keep_shown(&mut self) {
+        if !matches!(self.loading, Some(Load::Page { .. })) {
+            return;
+        }
+        self.loader.cancel();
+        if let Some(Load::Page {
+            shown: Some(page), ..
+        }) = self.loading.take()
+        {
+            let mut page = *page;
+            page.info.warning = Some("page is incomplete: stopped".to_string());
+            self.history.push(page);
+        }
+    }
+
+    pub fn load_page(&mut self, req: Request) -> io::Result<()> {
+        self.status(&format!("loading {}", req.url))?;
+        self.keep_line();
+        self.loader.start(req);
+        self.loading = Some(Load::Page {
+            builder: None,
+            shown: None,
+            drawn: Instant::now(),
+            bytes: 0,
+            drawn_bytes: 0,
+        });
+        Ok(())
+    }
+
+    // saves the response into dir, or the working directory, named after
+    // the url path
+    pub fn download(&mut self, req: Request, dir: Option<&Path>) -> io::Result<()> {
+        let name = match req.url.file_name() {
+            name if name.is_empty() => "index.html".to_string(),
+            name => name,
+        };
+        let name = match dir {
+            Some(dir) => dir.join(name).display().to_string(),
+            None => name,
+        };
+        self.save(req, name)
+    }
+
+    // saves the response as the file name
+    pub fn save(&mut self, req: Request, name: String) -> io::Result<()> {
+        self.status(&format!("downloading {}", req.url))?;
+        self.loader.start(req);
+        self.loading = Some(Load::Download {
+            name,
+            file: None,
+            bytes: 0,
+        });
+        Ok(())
+    }
+
+    // drops the fetch and puts back the current page as it was
+    pub fn cancel(&mut self) -> io::Result<()> {
+        self.loader.cancel();
+        match self.loading.take() {
+            Some(Load::Page { shown, .. }) => {
+                if shown.is_some() {
+                    self.line = self.history.line();
+                    self.forget_page();
+                    self.draw()?;
+                }
+                self.status("canceled")
+            }
+            Some(Load::Download { name, file, .. }) => {
+                discard(&name, file);
+                self.status("download canceled")
+            }
+            None => Ok(()),
+        }
+    }
+
+    // takes in what the fetch sent since last time, returns whether
+    // there was anything
+    pub fn poll(&mut self, rows: u16, visits: &mut Visits) -> io::Result<bool> {
+        let mut received = false;
+        while let Some(event) = self.loader.poll() {
+            self.on_event(event, visits)?;
+            received = true;
+        }
+        if received {
+            self.draw_loading(rows)?;
+        }
+        Ok(received)
+    }
+
+    fn on_event(&mut self, event: Event, visits: &mut Visits) -> io::Result<()> {
+        if let Event::Done(res) = event {
+            let load = self.loading.take();
+            return self.finish_load(load, res, visits);
+        }
+        let res = match (self.loading.as_mut(), event) {
+            (
+                Some(Load::Page { builder, .. }),
+                Event::Head {
+                    status,
+                    headers,
+                    url,
+                },
+            ) => {
+                *builder = Some(Box::new(PageBuilder::new(url, Some(status), headers)));
+                Ok(())
+            }
+            (Some(Load::Page { builder, bytes, .. }), Event::Data(data)) => {
+                *bytes += data.len();
+                if let Some(builder) = builder {
+                    builder.feed(&data);
+                }
+                Ok(())
+            }
+            // an error page is no file to save
+            (Some(Load::Download { name, .. }), Event::Head { status, .. })
+                if !(200..300).contains(&status) =>
+            {
+                Err(io::Error::other(format!(
+                    "{} not saved: status {}",
+                    name, status
+                )))
+            }
+            (Some(Load::Download { name, file, .. }), Event::Head { .. }) => {
+                File::create(part_name(name)).map(|f| *file = Some(f))
+            }
+            (Some(Load::Download { file, bytes, .. }), Event::Data(data)) => {
+                *bytes += data.len();
+                match file {
+                    Some(file) => file.write_all(&data),
+                    None => Ok(()),
+                }
+            }
+            _ => Ok(()),
+        };
+        if let Err(e) = res {
+            self.stop();
+            self.status(&e.to_string())?;
+        }
+        Ok(())
+    }
+
+    fn finish_load(
+        &mut self,
+        load: Option<Load>,
+        res: io::Result<()>,
+        visits: &mut Visits,
+    ) -> io::Result<()> {
+        match (load, res) {
+            (
+                Some(Load::Page {
+                    builder: Some(builder),
+                    shown,
+                    ..
+                }),
+                res,
+            ) => {
+                // a page too short to be drawn while loading starts at the top
+                if shown.is_none() {
+                    self.line = 0;
+                }
+                let mut page = builder.finish();
+                // whatever came in before the error is still worth showing
+                if let Err(e) = res {
+                    page.info.warning = Some(format!("page is incomplete: {}", e));
+                }
+                self.open(Ok(page), visits)
+            }
+            (Some(Load::Download { name, file, bytes }), Ok(())) => {
+                drop(file);
+                match fs::rename(part_name(&name), &name) {
+                    Ok(()) => self.status(&format!("saved {} ({} bytes)", name, bytes)),
+                    Err(e) => self.status(&format!("can't save {}: {}", name, e)),
+                }
+            }
+            (Some(Load::Download { name, file, .. }), Err(e)) => {
+                discard(&name, file);
+                self.status(&e.to_string())
+            }
+            (_, Err(e)) => self.status(&e.to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    // draws a loading page while it comes in. the whole page is rendered
+    // each time, so a page that fills the screen waits for REDRAW_INTERVAL
+    // and for its body to grow by a quarter, which keeps that from going
+    // quadratic on a big one. until then either will do.
+    fn draw_loading(&mut self, rows: u16) -> io::Result<()> {
+        let Some(Load::Page {
+            builder: Some(builder),
+            shown,
+            drawn,
+            bytes,
+            drawn_bytes,
+        }) = &mut self.loading
+        else {
+            return Ok(());
+        };
+        // nothing new, or nobody to see it
+        if !self.visible || *bytes == *drawn_bytes {
+            return Ok(());
+        }
+        let filling = shown
+            .as_ref()
+            .is_none_or(|page| page.line_count < rows.into());
+        let grown = *bytes > *drawn_bytes + *drawn_bytes / 4;
+        let due = drawn.elapsed() >= REDRAW_INTERVAL;
+        let redraw = match filling {
+            true => grown || due,
+            false => grown && due,
+        };
+        if !redraw {
+            return Ok(());
+        }
+        let page = builder.page();
+        *drawn_bytes = *bytes;
+        *drawn = Instant::now();
+        let first = shown.is_none();
+        if first && page.line_count == 0 {
+            return Ok(());
+        }
+        *shown = Some(Box::new(page));
+        if first {
+            self.line = 0;
+            self.forget_page();
+        }
+        self.draw()
+    }
+
+    // opens url again, as when the tab is restored
+    pub fn reopen(&mut self, url: Url, visits: &mut Visits) -> io::Result<()> {
+        match url.scheme() {
+            "about" => Ok(()),
+            "file" => {
+                let path = String::from_utf8_lossy(&url::percent_decode(url.path())).into_owned();
+                self.open_file(&path, visits)
+            }
+            _ => self.load_page(Request::get(url)),
+        }
+    }
+}
+
+// the tab bar: the title of every tab, the one on screen in brackets
+pub fn bar(tabs: &[Tab], active: usize) -> String {
+    let titles: Vec<String> = tabs
+        .iter()
+        .enumerate()
+        .map(|(i, tab)| {
+            let title: String = tab.shown().title().chars().take(20).collect();
+            match i == active {
+                true => format!("[{}: {}]", i, title),
+                false => format!(" {}: {} ", i, title),
+            }
+        })
+        .collect();
+    titles.join("")
+}
+
+// the open tabs are kept in the config dir, one url a line, with the one
+// on screen marked by a '*'
+pub fn save_session(tabs: &[Tab], active: usize) -> io::Result<()> {
+    let Some(dir) = config::dir() else {
+        return Ok(());
+    };
+    fs::create_dir_all(&dir)?;
+    let mut buf = String::new();
+    for (i, tab) in tabs.iter().enumerate() {
+        let mark = if i == active { "* " } else { "" };
+        buf.push_str(&format!("{}{}\n", mark, tab.history.current().info.url));
+    }
+    fs::write(dir.join("tabs"), buf)
+}
+
+// the urls of the tabs open last time, and which one was on screen
+pub fn load_session() -> (Vec<Url>, usize) {
+    let mut urls = Vec::new();
+    let mut active = 0;
+    let Some(buf) = config::dir().and_then(|dir| fs::read_to_string(dir.join("tabs")).ok()) else {
+        return (urls, active);
+    };
+    for line in buf.lines() {
+        let (on_screen, line) = match line.strip_prefix("* ") {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+        if let Ok(url) = Url::parse(line.trim()) {
+            if on_screen {
+                active = urls.len();
+            }
+            urls.push(url);
+        }
+    }
+    (urls, active)
+}
+
+// a download is written next to where it goes until it is all in
+fn part_name(name: &str) -> String {
+    format!("{}.part", name)
+}
+
+// don't leave half a file behind
+fn discard(name: &str, file: Option<File>) {
+    if file.is_some() {
+        drop(file);
+        let _ = fs::remove_file(part_name(name));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::tests::Pages;
+    use std::path::PathBuf;
+
+    // saves url as name in the pages' directory, as far as it gets
+    fn save(pages: &Pages, url: &str, name: &str) -> PathBuf {
+        let mut tab = Tab::new(pages.fetcher());
+        let mut visits = Visits::load(None);
+        let file = pages.0.join(name);
+        let req = Request::get(Url::parse(url).unwrap());
+        tab.save(req, file.display().to_string()).unwrap();
+        while tab.is_loading() {
+            tab.poll(24, &mut visits).unwrap();
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        file
+    }
+
+    #[test]
+    fn downloads_only_keep_what_came_in_whole() {
+        let pages = Pages::new("download", &[("site/f.txt", "hello")]);
+        let file = save(&pages, "http://site/f.txt", "f.txt");
+        assert_eq!(fs::read_to_string(&file).unwrap(), "hello");
+        assert!(!Path::new(&part_name(&file.display().to_string())).exists());
+
+        // an error page goes nowhere
+        let file = save(&pages, "http://site/missing", "missing");
+        assert!(!file.exists());
+        assert!(!Path::new(&part_name(&file.display().to_string())).exists());
+    }
+}
@@ -0,0 +1,916 @@
+// html tokenizer, following the states in the whatwg html spec (13.2.5).
+// the tokenizer is resumable: feed it input in pieces and pull tokens out,
+// it keeps its state when it runs out of characters.
+
+use crate::entities::{self, CharRef};
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tag {
+    pub name: String,
+    pub attrs: Vec<(String, String)>,
+    pub self_closing: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    Doctype(String),
+    StartTag(Tag),
+    EndTag(Tag),
+    Comment(String),
+    Text(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Data,
+    RcData,
+    RawText,
+    ScriptData,
+    ScriptDataEscaped,
+    ScriptDataDoubleEscaped,
+    PlainText,
+    TagOpen,
+    EndTagOpen,
+    TagName,
+    BeforeAttrName,
+    AttrName,
+    AfterAttrName,
+    BeforeAttrValue,
+    AttrValueDoubleQuoted,
+    AttrValueSingleQuoted,
+    AttrValueUnquoted,
+    AfterAttrValueQuoted,
+    SelfClosingStartTag,
+    BogusComment,
+    MarkupDeclarationOpen,
+    CommentStart,
+    CommentStartDash,
+    Comment,
+    CommentEndDash,
+    CommentEnd,
+    CommentEndBang,
+    Doctype,
+    BeforeDoctypeName,
+    DoctypeName,
+    AfterDoctypeName,
+    CdataSection,
+}
+
+pub struct Tokenizer {
+    input: Vec<char>,
+    pos: usize,
+    eof: bool,
+    state: State,
+    tokens: VecDeque<Token>,
+    text: String,
+    tag: Tag,
+    is_end_tag: bool,
+    attr_name: String,
+    attr_value: String,
+    comment: String,
+    doctype: String,
+    last_start_tag: String,
+    // the last character fed was a \r, so a \n starting the next feed
+    // belongs to it
+    after_cr: bool,
+}
+
+fn is_space(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\x0C' | ' ')
+}
+
+impl Tokenizer {
+    pub fn new() -> Tokenizer {
+        Tokenizer {
+            input: Vec::new(),
+            pos: 0,
+            eof: false,
+            state: State::Data,
+            tokens: VecDeque::new(),
+            text: String::new(),
+            tag: Tag::new(),
+            is_end_tag: false,
+            attr_name: String::new(),
+            attr_value: String::new(),
+            comment: String::new(),
+            doctype: String::new(),
+            last_start_tag: String::new(),
+            after_cr: false,
+        }
+    }
+
+    pub fn feed(&mut self, s: &str) {
+        self.input.drain(..self.pos);
+        self.pos = 0;
+        // \r\n and lone \r are line breaks like \n
+        for c in s.chars() {
+            match c {
+                '\n' if self.after_cr => (),
+                '\r' => self.input.push('\n'),
+                c => self.input.push(c),
+            }
+            self.after_cr = c == '\r';
+        }
+    }
+
+    pub fn end(&mut self) {
+        self.eof = true;
+    }
+
+    // the tree builder switches states for raw text elements, as in the spec
+    pub fn set_state(&mut self, state: State) {
+        self.state = state;
+    }
+
+    pub fn next_token(&mut self) -> Option<Token> {
+        while self.tokens.is_empty() {
+            if !self.step() {
+                break;
+            }
+        }
+        self.tokens.pop_front()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input.get(self.pos).copied()
+    }
+
+    fn consume(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    // Some(true) if the next characters match s, None if more input is needed
+    fn lookahead(&self, s: &str, ignore_case: bool) -> Option<bool> {
+        self.lookahead_at(self.pos, s, ignore_case)
+    }
+
+    fn lookahead_at(&self, at: usize, s: &str, ignore_case: bool) -> Option<bool> {
+        for (i, expected) in (at..).zip(s.chars()) {
+            let Some(&c) = self.input.get(i) else {
+                return if self.eof { Some(false) } else { None };
+            };
+            let ok = match ignore_case {
+                true => c.eq_ignore_ascii_case(&expected),
+                false => c == expected,
+            };
+            if !ok {
+                return Some(false);
+            }
+        }
+        Some(true)
+    }
+
+    fn flush_text(&mut self) {
+        if !self.text.is_empty() {
+            let text = std::mem::take(&mut self.text);
+            self.tokens.push_back(Token::Text(text));
+        }
+    }
+
+    fn emit(&mut self, token: Token) {
+        self.flush_text();
+        self.tokens.push_back(token);
+    }
+
+    fn new_tag(&mut self, is_end_tag: bool) {
+        self.tag = Tag::new();
+        self.is_end_tag = is_end_tag;
+    }
+
+    fn finish_attr(&mut self) {
+        if self.attr_name.is_empty() {
+            return;
+        }
+        let name = std::mem::take(&mut self.attr_name);
+        let value = std::mem::take(&mut self.attr_value);
+        // duplicate attributes are dropped, the first one wins
+        if !self.tag.attrs.iter().any(|(n, _)| *n == name) {
+            self.tag.attrs.push((name, value));
+        }
+    }
+
+    fn emit_tag(&mut self) {
+        self.finish_attr();
+        let tag = std::mem::replace(&mut self.tag, Tag::new());
+        if self.is_end_tag {
+            self.emit(Token::EndTag(tag));
+        } else {
+            self.last_start_tag = tag.name.clone();
+            self.emit(Token::StartTag(tag));
+        }
+    }
+
+    fn emit_comment(&mut self) {
+        let comment = std::mem::take(&mut self.comment);
+        self.emit(Token::Comment(comment));
+    }
+
+    fn emit_doctype(&mut self) {
+        let doctype = std::mem::take(&mut self.doctype);
+        self.emit(Token::Doctype(doctype));
+    }
+
+    // raw text ends only at an end tag matching the element that opened it
+    fn at_appropriate_end_tag(&self) -> Option<bool> {
+        let name = &self.last_start_tag;
+        match self.lookahead("/", false)? {
+            true => (),
+            false => return Some(false),
+        }
+        let mut i = self.pos + 1;
+        for expected in name.chars() {
+            let Some(&c) = self.input.get(i) else {
+                return if self.eof { Some(false) } else { None };
+            };
+            if !c.eq_ignore_ascii_case(&expected) {
+                return Some(false);
+            }
+            i += 1;
+        }
+        match self.input.get(i) {
+            Some(&c) => Some(is_space(c) || c == '/' || c == '>'),
+            None if self.eof => Some(false),
+            None => None,
+        }
+    }
+
+    // at the '<' of raw text, starts the end tag if it is the appropriate one
+    // and returns whether it did, None if more input is needed
+    fn raw_end_tag(&mut self) -> Option<bool> {
+        self.pos += 1;
+        let end = self.at_appropriate_end_tag();
+        if end != Some(true) {
+            self.pos -= 1;
+            return end;
+        }
+        self.pos += 1;
+        self.new_tag(true);
+        self.tag.name = self.last_start_tag.clone();
+        self.pos += self.last_start_tag.chars().count();
+        self.state = State::BeforeAttrName;
+        Some(true)
+    }
+
+    // "script" at input index at, ended as a tag name is
+    fn script_tag_at(&self, at: usize) -> Option<bool> {
+        if !self.lookahead_at(at, "script", true)? {
+            return Some(false);
+        }
+        match self.input.get(at + 6) {
+            Some(&c) => Some(is_space(c) || c == '/' || c == '>'),
+            None if self.eof => Some(false),
+            None => None,
+        }
+    }
+
+    // script data and its escapes: after a <!-- a <script> opens a nested
+    // one, and the </script> that closes that doesn't end the element. -->
+    // goes back to plain script data. returns false if more input is needed
+    fn script_data(&mut self) -> bool {
+        if self.state != State::ScriptData {
+            let Some(end) = self.lookahead("-->", false) else {
+                return false;
+            };
+            if end {
+                self.pos += 3;
+                self.text.push_str("-->");
+                self.state = State::ScriptData;
+                return true;
+            }
+        }
+        let c = self.peek().unwrap();
+        if c != '<' {
+            self.pos += 1;
+            self.text.push(c);
+            return true;
+        }
+        let (open, len, next) = match self.state {
+            State::ScriptData => match self.lookahead("<!--", false) {
+                // the dashes stay, so that <!--> closes at once
+                Some(true) => (Some(true), 2, State::ScriptDataEscaped),
+                Some(false) => (Some(false), 0, State::ScriptData),
+                None => (None, 0, State::ScriptData),
+            },
+            State::ScriptDataEscaped => (
+                self.script_tag_at(self.pos + 1),
+                7,
+                State::ScriptDataDoubleEscaped,
+            ),
+            _ => match self.lookahead_at(self.pos + 1, "/", false) {
+                Some(true) => (
+                    self.script_tag_at(self.pos + 2),
+                    8,
+                    State::ScriptDataEscaped,
+                ),
+                other => (other, 0, State::ScriptDataEscaped),
+            },
+        };
+        match open {
+            None => return false,
+            Some(true) => {
+                let taken: String = self.input[self.pos..self.pos + len].iter().collect();
+                self.text.push_str(&taken);
+                self.pos += len;
+                self.state = next;
+                return true;
+            }
+            Some(false) => (),
+        }
+        if self.state == State::ScriptDataDoubleEscaped {
+            self.pos += 1;
+            self.text.push('<');
+            return true;
+        }
+        match self.raw_end_tag() {
+            None => false,
+            Some(true) => true,
+            Some(false) => {
+                self.pos += 1;
+                self.text.push('<');
+                true
+            }
+        }
+    }
+
+    // decodes the reference at '&' into the text or the attribute value,
+    // returns false if more input is needed
+    fn char_ref(&mut self, in_attr: bool) -> bool {
+        let decoded = match entities::char_ref(&self.input[self.pos + 1..], in_attr, self.eof) {
+            CharRef::Incomplete => return false,
+            CharRef::NotARef => {
+                self.pos += 1;
+                "&".to_string()
+            }
+            CharRef::Ref(len, s) => {
+                self.pos += 1 + len;
+                s
+            }
+        };
+        match in_attr {
+            true => self.attr_value.push_str(&decoded),
+            false => self.text.push_str(&decoded),
+        }
+        true
+    }
+
+    // runs one step of the state machine, returns false when input is exhausted
+    fn step(&mut self) -> bool {
+        if self.pos >= self.input.len() {
+            if self.eof {
+                self.handle_eof();
+            }
+            return false;
+        }
+        match self.state {
+            State::Data => match self.peek().unwrap() {
+                '&' => return self.char_ref(false),
+                '<' => {
+                    self.pos += 1;
+                    self.state = State::TagOpen;
+                }
+                c => {
+                    self.pos += 1;
+                    self.text.push(c);
+                }
+            },
+            State::RcData | State::RawText => {
+                let c = self.peek().unwrap();
+                if c == '&' && self.state == State::RcData {
+                    return self.char_ref(false);
+                } else if c == '<' {
+                    match self.raw_end_tag() {
+                        None => return false,
+                        Some(true) => (),
+                        Some(false) => {
+                            self.pos += 1;
+                            self.text.push('<');
+                        }
+                    }
+                } else {
+                    self.pos += 1;
+                    self.text.push(c);
+                }
+            }
+            State::ScriptData | State::ScriptDataEscaped | State::ScriptDataDoubleEscaped => {
+                return self.script_data();
+            }
+            State::PlainText => {
+                let c = self.consume().unwrap();
+                self.text.push(c);
+            }
+            State::TagOpen => match self.peek().unwrap() {
+                '!' => {
+                    self.pos += 1;
+                    self.state = State::MarkupDeclarationOpen;
+                }
+                '/' => {
+                    self.pos += 1;
+                    self.state = State::EndTagOpen;
+                }
+                c if c.is_ascii_alphabetic() => {
+                    self.new_tag(false);
+                    self.state = State::TagName;
+                }
+                '?' => {
+                    self.comment.clear();
+                    self.state = State::BogusComment;
+                }
+                _ => {
+                    self.text.push('<');
+                    self.state = State::Data;
+                }
+            },
+            State::EndTagOpen => match self.peek().unwrap() {
+                c if c.is_ascii_alphabetic() => {
+                    self.new_tag(true);
+                    self.state = State::TagName;
+                }
+                '>' => {
+                    self.pos += 1;
+                    self.state = State::Data;
+                }
+                _ => {
+                    self.comment.clear();
+                    self.state = State::BogusComment;
+                }
+            },
+            State::TagName => match self.consume().unwrap() {
+                c if is_space(c) => self.state = State::BeforeAttrName,
+                '/' => self.state = State::SelfClosingStartTag,
+                '>' => {
+                    self.state = State::Data;
+                    self.emit_tag();
+                }
+                '\0' => self.tag.name.push('\u{FFFD}'),
+                c => self.tag.name.push(c.to_ascii_lowercase()),
+            },
+            State::BeforeAttrName => match self.peek().unwrap() {
+                c if is_space(c) => self.pos += 1,
+                '/' | '>' => self.state = State::AfterAttrName,
+                '=' => {
+                    self.pos += 1;
+                    self.finish_attr();
+                    self.attr_name.push('=');
+                    self.state = State::AttrName;
+                }
+                _ => {
+                    self.finish_attr();
+                    self.state = State::AttrName;
+                }
+            },
+            State::AttrName => match self.peek().unwrap() {
+                c if is_space(c) || c == '/' || c == '>' => self.state = State::AfterAttrName,
+                '=' => {
+                    self.pos += 1;
+                    self.state = State::BeforeAttrValue;
+                }
+                c => {
+                    self.pos += 1;
+                    match c {
+                        '\0' => self.attr_name.push('\u{FFFD}'),
+                        c => self.attr_name.push(c.to_ascii_lowercase()),
+                    }
+                }
+            },
+            State::AfterAttrName => match self.peek().unwrap() {
+                c if is_space(c) => self.pos += 1,
+                '/' => {
+                    self.pos += 1;
+                    self.state = State::SelfClosingStartTag;
+                }
+                '=' => {
+                    self.pos += 1;
+                    self.state = State::BeforeAttrValue;
+                }
+                '>' => {
+                    self.pos += 1;
+                    self.state = State::Data;
+                    self.emit_tag();
+                }
+                _ => {
+                    self.finish_attr();
+                    self.state = State::AttrName;
+                }
+            },
+            State::BeforeAttrValue => match self.peek().unwrap() {
+                c if is_space(c) => self.pos += 1,
+                '"' => {
+                    self.pos += 1;
+                    self.state = State::AttrValueDoubleQuoted;
+                }
+                '\'' => {
+                    self.pos += 1;
+                    self.state = State::AttrValueSingleQuoted;
+                }
+                '>' => {
+                    self.pos += 1;
+                    self.state = State::Data;
+                    self.emit_tag();
+                }
+                _ => self.state = State::AttrValueUnquoted,
+            },
+            State::AttrValueDoubleQuoted => match self.peek().unwrap() {
+                '&' => return self.char_ref(true),
+                c => {
+                    self.pos += 1;
+                    match c {
+                        '"' => self.state = State::AfterAttrValueQuoted,
+                        '\0' => self.attr_value.push('\u{FFFD}'),
+                        c => self.attr_value.push(c),
+                    }
+                }
+            },
+            State::AttrValueSingleQuoted => match self.peek().unwrap() {
+                '&' => return self.char_ref(true),
+                c => {
+                    self.pos += 1;
+                    match c {
+                        '\'' => self.state = State::AfterAttrValueQuoted,
+                        '\0' => self.attr_value.push('\u{FFFD}'),
+                        c => self.attr_value.push(c),
+                    }
+                }
+            },
+            State::AttrValueUnquoted => match self.peek().unwrap() {
+                '&' => return self.char_ref(true),
+                c => {
+                    self.pos += 1;
+                    match c {
+                        c if is_space(c) => self.state = State::BeforeAttrName,
+                        '>' => {
+                            self.state = State::Data;
+                            self.emit_tag();
+                        }
+                        '\0' => self.attr_value.push('\u{FFFD}'),
+                        c => self.attr_value.push(c),
+                    }
+                }
+            },
+            State::AfterAttrValueQuoted => match self.peek().unwrap() {
+                c if is_space(c) => {
+                    self.pos += 1;
+                    self.state = State::BeforeAttrName;
+                }
+                '/' => {
+                    self.pos += 1;
+                    self.state = State::SelfClosingStartTag;
+                }
+                '>' => {
+                    self.pos += 1;
+                    self.state = State::Data;
+                    self.emit_tag();
+                }
+                _ => self.state = State::BeforeAttrName,
+            },
+            State::SelfClosingStartTag => match self.peek().unwrap() {
+                '>' => {
+                    self.pos += 1;
+                    self.tag.self_closing = true;
+                    self.state = State::Data;
+                    self.emit_tag();
+                }
+                _ => self.state = State::BeforeAttrName,
+            },
+            State::BogusComment => match self.consume().unwrap() {
+                '>' => {
+                    self.state = State::Data;
+                    self.emit_comment();
+                }
+                '\0' => self.comment.push('\u{FFFD}'),
+                c => self.comment.push(c),
+            },
+            State::MarkupDeclarationOpen => {
+                let Some(comment) = self.lookahead("--", false) else {
+                    return false;
+                };
+                if comment {
+                    self.pos += 2;
+                    self.comment.clear();
+                    self.state = State::CommentStart;
+                    return true;
+                }
+                let Some(doctype) = self.lookahead("DOCTYPE", true) else {
+                    return false;
+                };
+                if doctype {
+                    self.pos += 7;
+                    self.state = State::Doctype;
+                    return true;
+                }
+                let Some(cdata) = self.lookahead("[CDATA[", false) else {
+                    return false;
+                };
+                if cdata {
+                    self.pos += 7;
+                    self.state = State::CdataSection;
+                    return true;
+                }
+                self.comment.clear();
+                self.state = State::BogusComment;
+            }
+            State::CommentStart => match self.peek().unwrap() {
+                '-' => {
+                    self.pos += 1;
+                    self.state = State::CommentStartDash;
+                }
+                '>' => {
+                    self.pos += 1;
+                    self.state = State::Data;
+                    self.emit_comment();
+                }
+                _ => self.state = State::Comment,
+            },
+            State::CommentStartDash => match self.peek().unwrap() {
+                '-' => {
+                    self.pos += 1;
+                    self.state = State::CommentEnd;
+                }
+                '>' => {
+                    self.pos += 1;
+                    self.state = State::Data;
+                    self.emit_comment();
+                }
+                _ => {
+                    self.comment.push('-');
+                    self.state = State::Comment;
+                }
+            },
+            State::Comment => match self.consume().unwrap() {
+                '-' => self.state = State::CommentEndDash,
+                '\0' => self.comment.push('\u{FFFD}'),
+                c => self.comment.push(c),
+            },
+            State::CommentEndDash => match self.peek().unwrap() {
+                '-' => {
+                    self.pos += 1;
+                    self.state = State::CommentEnd;
+                }
+                _ => {
+                    self.comment.push('-');
+                    self.state = State::Comment;
+                }
+            },
+            State::CommentEnd => match self.peek().unwrap() {
+                '>' => {
+                    self.pos += 1;
+                    self.state = State::Data;
+                    self.emit_comment();
+                }
+                '!' => {
+                    self.pos += 1;
+                    self.state = State::CommentEndBang;
+                }
+                '-' => {
+                    self.pos += 1;
+                    self.comment.push('-');
+                }
+                _ => {
+                    self.comment.push_str("--");
+                    self.state = State::Comment;
+                }
+            },
+            State::CommentEndBang => match self.peek().unwrap() {
+                '-' => {
+                    self.pos += 1;
+                    self.comment.push_str("--!");
+                    self.state = State::CommentEndDash;
+                }
+                '>' => {
+                    self.pos += 1;
+                    self.state = State::Data;
+                    self.emit_comment();
+                }
+                _ => {
+                    self.comment.push_str("--!");
+                    self.state = State::Comment;
+                }
+            },
+            State::Doctype => {
+                if is_space(self.peek().unwrap()) {
+                    self.pos += 1;
+                }
+                self.doctype.clear();
+                self.state = State::BeforeDoctypeName;
+            }
+            State::BeforeDoctypeName => match self.peek().unwrap() {
+                c if is_space(c) => self.pos += 1,
+                '>' => {
+                    self.pos += 1;
+                    self.state = State::Data;
+                    self.emit_doctype();
+                }
+                _ => self.state = State::DoctypeName,
+            },
+            State::DoctypeName => match self.consume().unwrap() {
+                c if is_space(c) => self.state = State::AfterDoctypeName,
+                '>' => {
+                    self.state = State::Data;
+                    self.emit_doctype();
+                }
+                '\0' => self.doctype.push('\u{FFFD}'),
+                c => self.doctype.push(c.to_ascii_lowercase()),
+            },
+            // public and system identifiers are of no use to us, skip them
+            State::AfterDoctypeName => {
+                if self.consume().unwrap() == '>' {
+                    self.state = State::Data;
+                    self.emit_doctype();
+                }
+            }
+            State::CdataSection => {
+                let Some(end) = self.lookahead("]]>", false) else {
+                    return false;
+                };
+                if end {
+                    self.pos += 3;
+                    self.state = State::Data;
+                } else {
+                    let c = self.consume().unwrap();
+                    self.text.push(c);
+                }
+            }
+        }
+        true
+    }
+
+    fn handle_eof(&mut self) {
+        match self.state {
+            State::TagOpen => self.text.push('<'),
+            State::EndTagOpen => self.text.push_str("</"),
+            State::BogusComment
+            | State::MarkupDeclarationOpen
+            | State::CommentStart
+            | State::CommentStartDash
+            | State::Comment
+            | State::CommentEndDash
+            | State::CommentEnd
+            | State::CommentEndBang => self.emit_comment(),
+            State::Doctype
+            | State::BeforeDoctypeName
+            | State::DoctypeName
+            | State::AfterDoctypeName => self.emit_doctype(),
+            _ => (),
+        }
+        self.state = State::Data;
+        self.flush_text();
+    }
+}
+
+impl Tag {
+    pub fn new() -> Tag {
+        Tag {
+            name: String::new(),
+            attrs: Vec::new(),
+            self_closing: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens_in_pieces(html: &str, size: usize) -> Vec<Token> {
+        let mut tokenizer = Tokenizer::new();
+        let chars: Vec<char> = html.chars().collect();
+        let mut tokens = Vec::new();
+        for piece in chars.chunks(size) {
+            tokenizer.feed(&piece.iter().collect::<String>());
+            while let Some(token) = tokenizer.next_token() {
+                tokens.push(token);
+            }
+        }
+        tokenizer.end();
+        while let Some(token) = tokenizer.next_token() {
+            tokens.push(token);
+        }
+        tokens
+    }
+
+    // the tokens of html, which have to be the same however it is cut up
+    fn tokens(html: &str) -> Vec<Token> {
+        let whole = tokens_in_pieces(html, html.len().max(1));
+        assert_eq!(
+            whole,
+            tokens_in_pieces(html, 1),
+            "fed a character at a time"
+        );
+        assert_eq!(whole, tokens_in_pieces(html, 3), "fed three at a time");
+        whole
+    }
+
+    fn text(s: &str) -> Token {
+        Token::Text(s.to_string())
+    }
+
+    fn comment(s: &str) -> Token {
+        Token::Comment(s.to_string())
+    }
+
+    fn start(name: &str, attrs: &[(&str, &str)]) -> Token {
+        Token::StartTag(Tag {
+            name: name.to_string(),
+            attrs: attrs
+                .iter()
+                .map(|&(n, v)| (n.to_string(), v.to_string()))
+                .collect(),
+            self_closing: false,
+        })
+    }
+
+    fn end(name: &str) -> Token {
+        Token::EndTag(Tag {
+            name: name.to_string(),
+            ..Tag::new()
+        })
+    }
+
+    #[test]
+    fn line_breaks() {
+        assert_eq!(
+            tokens("a\r\nb\rc\n\r\n\r\r<p title='x\r\ny'>"),
+            [text("a\nb\nc\n\n\n\n"), start("p", &[("title", "x\ny")])]
+        );
+    }
+
+    #[test]
+    fn comments() {
+        assert_eq!(
+            tokens("a<!-- x -->b"),
+            [text("a"), comment(" x "), text("b")]
+        );
+        assert_eq!(tokens("<!-- -- -->"), [comment(" -- ")]);
+        assert_eq!(tokens("<!---->"), [comment("")]);
+        assert_eq!(tokens("<!-->x"), [comment(""), text("x")]);
+        assert_eq!(tokens("<!--a--!>b"), [comment("a"), text("b")]);
+        assert_eq!(tokens("<?xml x?>"), [comment("?xml x?")]);
+        assert_eq!(tokens("<!-- open"), [comment(" open")]);
+    }
+
+    #[test]
+    fn cdata_is_text() {
+        assert_eq!(tokens("<![CDATA[x<y&amp;]]>z"), [text("x<y&amp;z")]);
+    }
+
+    #[test]
+    fn markup_in_attribute_values() {
+        assert_eq!(
+            tokens(r#"<a title="<a" href='x>y' data=a<b>t"#),
+            [
+                start("a", &[("title", "<a"), ("href", "x>y"), ("data", "a<b")]),
+                text("t")
+            ]
+        );
+    }
+
+    #[test]
+    fn upper_case_and_duplicate_attributes() {
+        assert_eq!(
+            tokens("<DIV Class=x CLASS=y ID='z'></Div>"),
+            [start("div", &[("class", "x"), ("id", "z")]), end("div")]
+        );
+    }
+
+    #[test]
+    fn broken_tags() {
+        assert_eq!(tokens("a < b"), [text("a < b")]);
+        assert_eq!(tokens("a <"), [text("a <")]);
+        assert_eq!(tokens("</>x"), [text("x")]);
+        assert_eq!(
+            tokens("<br/>"),
+            [Token::StartTag(Tag {
+                name: "br".to_string(),
+                attrs: Vec::new(),
+                self_closing: true,
+            })]
+        );
+    }
+
+    #[test]
+    fn raw_text_ends_at_its_own_end_tag() {
+        let mut tokenizer = Tokenizer::new();
+        tokenizer.feed("a<b</p></STYLE >c");
+        tokenizer.set_state(State::RawText);
+        tokenizer.last_start_tag = "style".to_string();
+        assert_eq!(tokenizer.next_token(), Some(text("a<b</p>")));
+        assert_eq!(tokenizer.next_token(), Some(end("style")));
+        tokenizer.end();
+        assert_eq!(tokenizer.next_token(), Some(text("c")));
+    }
+
+    #[test]
+    fn entities_without_semicolons() {
+        assert_eq!(
+            tokens("&amp &lt;&copy2 &notit; &bogus; &#65&#x42;"),
+            [text("& <©2 ¬it; &bogus; AB")]
+        );
+        // in an attribute, a reference followed by = or a letter stays
+        assert_eq!(
+            tokens("<a href='?a=1&copy=2&amp;b&lt'>"),
+            [start("a", &[("href", "?a=1&copy=2&b<")])]
+        );
+    }
+}
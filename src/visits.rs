@@ -0,0 +1,271 @@
+// every page visited, kept across runs in a log that is only ever appended
+// to. a line is one visit: the time, the url and the title, tab separated.
+
+use crate::cookie;
+use crate::url::Url;
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+pub struct Visit {
+    pub url: String,
+    pub title: String,
+    // the last time it was visited
+    pub time: i64,
+    pub count: u32,
+}
+
+pub struct Visits {
+    file: Option<PathBuf>,
+    visits: Vec<Visit>,
+    // where each url is in visits
+    index: HashMap<String, usize>,
+}
+
+impl Visits {
+    pub fn load(file: Option<PathBuf>) -> Visits {
+        let mut visits = Visits {
+            file,
+            visits: Vec::new(),
+            index: HashMap::new(),
+        };
+        if let Some(text) = visits
+            .file
+            .as_ref()
+            .and_then(|f| fs::read_to_string(f).ok())
+        {
+            for line in text.lines() {
+                let mut parts = line.splitn(3, '\t');
+                let (Some(time), Some(url)) = (parts.next(), parts.next()) else {
+                    continue;
+                };
+                let Ok(time) = time.parse() else {
+                    continue;
+                };
+                visits.count(url, parts.next().unwrap_or_default(), time);
+            }
+        }
+        visits
+    }
+
+    fn count(&mut self, url: &str, title: &str, time: i64) {
+        match self.index.get(url) {
+            Some(&i) => {
+                let visit = &mut self.visits[i];
+                visit.count += 1;
+                visit.time = visit.time.max(time);
+                if !title.is_empty() {
+                    visit.title = title.to_string();
+                }
+            }
+            None => {
+                self.index.insert(url.to_string(), self.visits.len());
+                self.visits.push(Visit {
+                    url: url.to_string(),
+                    title: title.to_string(),
+                    time,
+                    count: 1,
+                });
+            }
+        }
+    }
+
+    pub fn add(&mut self, url: &Url, title: &str) -> io::Result<()> {
+        if url.scheme() == "about" {
+            return Ok(());
+        }
+        let url = url.to_string();
+        // a tab or a line break in the title would break the line up
+        let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+        let time = cookie::now();
+        self.count(&url, &title, time);
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut f = OpenOptions::new().create(true).append(true).open(file)?;
+        writeln!(f, "{}\t{}\t{}", time, url, title)
+    }
+
+    // the visits whose url or title has every word of query in it, most
+    // visited first
+    pub fn search(&self, query: &str) -> Vec<&Visit> {
+        let words: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+        let mut found: Vec<&Visit> = self
+            .visits
+            .iter()
+            .filter(|v| {
+                let url = v.url.to_lowercase();
+                let title = v.title.to_lowercase();
+                words.iter().all(|w| url.contains(w) || title.contains(w))
+            })
+            .collect();
+        found.sort_by(|a, b| b.count.cmp(&a.count).then(b.time.cmp(&a.time)));
+        found
+    }
+
+    // the urls that what is typed so far could be the start of, with or
+    // without the scheme and www.
+    pub fn complete(&self, typed: &str) -> Vec<String> {
+        if typed.is_empty() {
+            return Vec::new();
+        }
+        let typed = typed.to_lowercase();
+        self.search("")
+            .into_iter()
+            .filter(|v| {
+                let url = v.url.to_lowercase();
+                let rest = url.split_once("://").map_or(url.as_str(), |(_, rest)| rest);
+                url.starts_with(&typed)
+                    || rest.starts_with(&typed)
+                    || rest
+                        .strip_prefix("www.")
+                        .is_some_and(|r| r.starts_with(&typed))
+            })
+            .map(|v| v.url.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a log file with text in it, removed when done
+    struct Log(PathBuf);
+
+    impl Log {
+        fn new(name: &str, text: &str) -> Log {
+            let file = std::env::temp_dir().join(format!("ibrow-{}-{}", name, std::process::id()));
+            fs::write(&file, text).unwrap();
+            Log(file)
+        }
+    }
+
+    impl Drop for Log {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    const LOG: &str = "100\thttps://www.rust-lang.org/\tRust
+200\thttps://example.com/a\tExample A
+300\thttps://www.rust-lang.org/learn\tLearn Rust
+400\thttps://www.rust-lang.org/\tRust Programming Language
+500\thttps://example.com/a\t
+600\thttps://example.com/a
+700\thttp://rustup.rs/\trustup
+";
+
+    fn urls(visits: Vec<&Visit>) -> Vec<&str> {
+        visits.into_iter().map(|v| v.url.as_str()).collect()
+    }
+
+    #[test]
+    fn visits_of_a_url_add_up() {
+        let log = Log::new("visits-add-up", LOG);
+        let visits = Visits::load(Some(log.0.clone()));
+        let found = visits.search("example");
+        let a = found[0];
+        assert_eq!((a.count, a.time), (3, 600));
+        // a visit with no title keeps the one before
+        assert_eq!(a.title, "Example A");
+        let rust = visits.search("programming")[0];
+        assert_eq!((rust.count, rust.time), (2, 400));
+        assert_eq!(rust.title, "Rust Programming Language");
+    }
+
+    #[test]
+    fn search_puts_the_most_visited_first() {
+        let log = Log::new("visits-search", LOG);
+        let visits = Visits::load(Some(log.0.clone()));
+        assert_eq!(
+            urls(visits.search("")),
+            [
+                "https://example.com/a",
+                "https://www.rust-lang.org/",
+                // then the latest
+                "http://rustup.rs/",
+                "https://www.rust-lang.org/learn",
+            ]
+        );
+        // every word in the url or the title, in any case
+        assert_eq!(
+            urls(visits.search("RUST learn")),
+            ["https://www.rust-lang.org/learn"]
+        );
+        assert_eq!(urls(visits.search("rust lang")).len(), 2);
+        assert!(visits.search("rust python").is_empty());
+    }
+
+    #[test]
+    fn complete_with_or_without_the_scheme() {
+        let log = Log::new("visits-complete", LOG);
+        let visits = Visits::load(Some(log.0.clone()));
+        let rust = [
+            "https://www.rust-lang.org/",
+            "https://www.rust-lang.org/learn",
+        ];
+        assert_eq!(visits.complete("rust-"), rust);
+        assert_eq!(visits.complete("www.rust-"), rust);
+        assert_eq!(visits.complete("HTTPS://www.Rust-"), rust);
+        assert_eq!(
+            visits.complete("rust"),
+            [rust[0], "http://rustup.rs/", rust[1]]
+        );
+        assert_eq!(visits.complete("http:"), ["http://rustup.rs/"]);
+        assert!(visits.complete("lang").is_empty());
+        assert!(visits.complete("").is_empty());
+    }
+
+    #[test]
+    fn lines_that_are_no_good_are_passed_over() {
+        let log = Log::new(
+            "visits-malformed",
+            "\n\
+             just a url\n\
+             yesterday\thttps://a.example/\tA\n\
+             100\n\
+             200\thttps://b.example/\tB\textra\ttabs\n\
+             \u{0}\u{ff}\t\t\n\
+             300\thttps://c.example/",
+        );
+        let visits = Visits::load(Some(log.0.clone()));
+        assert_eq!(
+            urls(visits.search("")),
+            ["https://c.example/", "https://b.example/"]
+        );
+        assert_eq!(visits.search("b.example")[0].title, "B\textra\ttabs");
+        // nor is a log that isn't there, or isn't text
+        assert!(Visits::load(Some(log.0.join("nothing")))
+            .search("")
+            .is_empty());
+        fs::write(&log.0, b"100\thttps://a.example/\t\xff\n").unwrap();
+        assert!(Visits::load(Some(log.0.clone())).search("").is_empty());
+    }
+
+    #[test]
+    fn added_visits_are_logged() {
+        let log = Log::new("visits-logged", "");
+        let mut visits = Visits::load(Some(log.0.clone()));
+        let url = |s| Url::parse(s).unwrap();
+        visits
+            .add(&url("https://a.example/"), "A\ttitle\non two lines")
+            .unwrap();
+        visits.add(&url("about:blank"), "").unwrap();
+        visits.add(&url("https://a.example/"), "A").unwrap();
+
+        let loaded = Visits::load(Some(log.0.clone()));
+        for visits in [&visits, &loaded] {
+            let found = visits.search("");
+            assert_eq!(urls(found.clone()), ["https://a.example/"]);
+            assert_eq!((found[0].count, found[0].title.as_str()), (2, "A"));
+        }
+        let text = fs::read_to_string(&log.0).unwrap();
+        assert_eq!(text.lines().count(), 2);
+        assert!(text.contains("\thttps://a.example/\tA title on two lines\n"));
+    }
+}